@@ -0,0 +1,161 @@
+use std::fs;
+use std::io;
+use std::time::{Duration, Instant};
+
+pub const CONFIG_FILE: &str = "boidsound.cfg";
+
+// Position des seuils entre le bruit de fond et le pic mesures
+const THRESHOLD_RATIOS: [f32; 3] = [0.1, 0.35, 0.65];
+
+// Collects the audio levels seen during a calibration window.
+pub struct Calibration {
+    levels: Vec<f32>,
+    started: Instant,
+    duration: Duration,
+}
+
+impl Calibration {
+    pub fn new(duration: Duration) -> Self {
+        Calibration {
+            levels: Vec::new(),
+            started: Instant::now(),
+            duration,
+        }
+    }
+
+    pub fn push(&mut self, level: f32) {
+        if !self.is_done() {
+            self.levels.push(level);
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.started.elapsed() >= self.duration
+    }
+
+    // Noise floor is the median level, peak is the 99th percentile so a
+    // single click does not stretch the whole range.
+    pub fn noise_floor_and_peak(&self) -> Option<(f32, f32)> {
+        if self.levels.is_empty() {
+            return None;
+        }
+        let mut sorted = self.levels.clone();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let percentile = |p: f32| sorted[((sorted.len() - 1) as f32 * p).round() as usize];
+        Some((percentile(0.5), percentile(0.99)))
+    }
+
+    pub fn thresholds(&self) -> Option<[f32; 3]> {
+        let (floor, peak) = self.noise_floor_and_peak()?;
+        if peak <= 0.0 {
+            return None;
+        }
+        // Si la piece est restee silencieuse, on garde au moins un ecart egal au bruit de fond
+        let span = (peak - floor).max(floor);
+        Some(THRESHOLD_RATIOS.map(|ratio| floor + span * ratio))
+    }
+}
+
+// Reads `key=value` lines from the config file, ignoring blanks and comments.
+pub fn load_config(path: &str) -> Vec<String> {
+    fs::read_to_string(path)
+        .map(|content| {
+            content
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// Writes the thresholds to the config file, keeping any other settings in it.
+pub fn save_thresholds(path: &str, thresholds: &[f32; 3]) -> io::Result<()> {
+    let mut lines: Vec<String> = fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| !line.trim().starts_with("freq_thresholds="))
+        .map(String::from)
+        .collect();
+    lines.push(format!("freq_thresholds={}", format_thresholds(thresholds)));
+    fs::write(path, lines.join("\n") + "\n")
+}
+
+pub fn format_thresholds(thresholds: &[f32; 3]) -> String {
+    format!(
+        "{:.5},{:.5},{:.5}",
+        thresholds[0], thresholds[1], thresholds[2]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measured(levels: &[f32]) -> Calibration {
+        let mut calibration = Calibration::new(Duration::from_secs(3600));
+        for &level in levels {
+            calibration.push(level);
+        }
+        calibration
+    }
+
+    #[test]
+    fn thresholds_from_median_and_99th_percentile() {
+        // 90 blocs de bruit, 10 de son, un clic isole ignore par le 99e centile
+        let mut levels = vec![0.01; 90];
+        levels.extend([0.1; 10]);
+        levels.push(1.0);
+        let calibration = measured(&levels);
+        assert_eq!(calibration.noise_floor_and_peak(), Some((0.01, 0.1)));
+        let thresholds = calibration.thresholds().unwrap();
+        for (threshold, expected) in thresholds.iter().zip([0.019, 0.0415, 0.0685]) {
+            assert!((threshold - expected).abs() < 1e-6, "{:?}", thresholds);
+        }
+
+        // Piece silencieuse mais bruitee : l'ecart vaut au moins le bruit de fond
+        let thresholds = measured(&[0.02; 50]).thresholds().unwrap();
+        for (threshold, expected) in thresholds.iter().zip([0.022, 0.027, 0.033]) {
+            assert!((threshold - expected).abs() < 1e-6, "{:?}", thresholds);
+        }
+
+        // Rien de mesure, ou aucun signal
+        assert!(measured(&[]).thresholds().is_none());
+        assert!(measured(&[0.0; 10]).thresholds().is_none());
+    }
+
+    #[test]
+    fn save_keeps_the_other_settings() {
+        let path = std::env::temp_dir().join("boidsound_calibrate_test.cfg");
+        let path = path.to_str().unwrap();
+        fs::write(
+            path,
+            "num_boids=40\n# seuils de la salle\nfreq_thresholds=1,2,3\nboid_speed=2\n",
+        )
+        .unwrap();
+        save_thresholds(path, &[0.01, 0.02, 0.03]).unwrap();
+        let content = fs::read_to_string(path).unwrap();
+        assert_eq!(
+            content,
+            "num_boids=40\n# seuils de la salle\nboid_speed=2\nfreq_thresholds=0.01000,0.02000,0.03000\n"
+        );
+        assert_eq!(
+            load_config(path),
+            [
+                "num_boids=40",
+                "boid_speed=2",
+                "freq_thresholds=0.01000,0.02000,0.03000"
+            ]
+        );
+
+        // Sans fichier, il est cree avec les seuils seuls
+        fs::remove_file(path).unwrap();
+        save_thresholds(path, &[0.5, 1.0, 1.5]).unwrap();
+        assert_eq!(
+            fs::read_to_string(path).unwrap(),
+            "freq_thresholds=0.50000,1.00000,1.50000\n"
+        );
+        fs::remove_file(path).ok();
+    }
+}
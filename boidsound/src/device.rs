@@ -0,0 +1,375 @@
+use cpal::traits::{DeviceTrait, HostTrait};
+use cpal::{
+    Device, FromSample, InputCallbackInfo, OutputCallbackInfo, Sample, SampleFormat, SampleRate,
+    SizedSample, Stream, StreamConfig, StreamError, SupportedStreamConfig,
+};
+
+// Taille reservee pour les buffers de conversion, pour ne pas allouer dans le callback :
+// les callbacks plus longs sont traites en plusieurs blocs
+const BUFFER_CAPACITY: usize = 16384;
+
+// Input settings picked on the command line: `device=`, `channels=` and `sample_rate=`.
+#[derive(Clone, Default)]
+pub struct InputOptions {
+    pub device: Option<String>,
+    pub channels: Option<u16>,
+    pub sample_rate: Option<u32>,
+}
+
+impl InputOptions {
+    pub fn from_args(args: &[String]) -> Self {
+        let value = |key: &str| {
+            args.iter()
+                .find(|arg| arg.starts_with(key))
+                .and_then(|arg| arg.split('=').nth(1))
+                .map(String::from)
+        };
+        InputOptions {
+            device: value("device="),
+            channels: value("channels=").and_then(|val| val.parse().ok()),
+            sample_rate: value("sample_rate=").and_then(|val| val.parse().ok()),
+        }
+    }
+}
+
+// Prints every input device of the default host with the configurations it supports.
+pub fn list_devices() {
+    let host = cpal::default_host();
+    println!("Host: {}", host.id().name());
+    let default_name = host.default_input_device().and_then(|d| d.name().ok());
+
+    let devices = match host.input_devices() {
+        Ok(devices) => devices,
+        Err(err) => {
+            eprintln!("Failed to enumerate input devices: {}", err);
+            return;
+        }
+    };
+
+    let mut found = false;
+    for device in devices {
+        found = true;
+        let name = device.name().unwrap_or_else(|_| String::from("<unknown>"));
+        let marker = if Some(&name) == default_name.as_ref() {
+            " (default)"
+        } else {
+            ""
+        };
+        println!("  {}{}", name, marker);
+        if let Ok(configs) = device.supported_input_configs() {
+            for config in configs {
+                println!(
+                    "      {} channels, {}-{} Hz, {:?}",
+                    config.channels(),
+                    config.min_sample_rate().0,
+                    config.max_sample_rate().0,
+                    config.sample_format()
+                );
+            }
+        }
+    }
+    if !found {
+        println!("  No input device available");
+    }
+}
+
+// Finds the requested device (exact name first, then a case-insensitive match
+// on part of the name) and a configuration satisfying the options.
+pub fn open_input(options: &InputOptions) -> Result<(Device, SupportedStreamConfig), String> {
+    let host = cpal::default_host();
+    let device = match &options.device {
+        None => host
+            .default_input_device()
+            .ok_or_else(|| String::from("No input device available"))?,
+        Some(wanted) => {
+            let devices = host
+                .input_devices()
+                .map_err(|err| format!("Failed to enumerate input devices: {}", err))?;
+            find_device(devices.collect(), wanted, "input")?
+        }
+    };
+
+    let config = choose_config(&device, options)?;
+    Ok((device, config))
+}
+
+fn find_device(devices: Vec<Device>, wanted: &str, kind: &str) -> Result<Device, String> {
+    let names: Vec<String> = devices
+        .iter()
+        .map(|d| d.name().unwrap_or_default())
+        .collect();
+    let index = match_name(&names, wanted).ok_or_else(|| {
+        format!(
+            "No {} device matching '{}' (run with list-devices)",
+            kind, wanted
+        )
+    })?;
+    Ok(devices.into_iter().nth(index).unwrap())
+}
+
+// Opens the output device named by `output=` (or the default one) at the
+// sample rate of the recording, for the replay.
+pub fn open_output(
+    name: Option<&str>,
+    sample_rate: u32,
+) -> Result<(Device, SupportedStreamConfig), String> {
+    let host = cpal::default_host();
+    let device = match name {
+        None => host
+            .default_output_device()
+            .ok_or_else(|| String::from("No output device available"))?,
+        Some(wanted) => {
+            let devices = host
+                .output_devices()
+                .map_err(|err| format!("Failed to enumerate output devices: {}", err))?;
+            find_device(devices.collect(), wanted, "output")?
+        }
+    };
+
+    let mut ranges: Vec<_> = device
+        .supported_output_configs()
+        .map_err(|err| format!("Failed to query output configurations: {}", err))?
+        .collect();
+    ranges.sort_by_key(|range| range.sample_format() != SampleFormat::F32);
+    let config = ranges
+        .into_iter()
+        .find_map(|range| range.try_with_sample_rate(SampleRate(sample_rate)))
+        .ok_or_else(|| format!("Output device does not support {} Hz", sample_rate))?;
+    Ok((device, config))
+}
+
+fn choose_config(device: &Device, options: &InputOptions) -> Result<SupportedStreamConfig, String> {
+    if options.channels.is_none() && options.sample_rate.is_none() {
+        return device
+            .default_input_config()
+            .map_err(|err| format!("Failed to get default input format: {}", err));
+    }
+
+    let default = device.default_input_config().ok();
+    let sample_rate = options
+        .sample_rate
+        .or_else(|| default.as_ref().map(|c| c.sample_rate().0));
+    let mut ranges: Vec<_> = device
+        .supported_input_configs()
+        .map_err(|err| format!("Failed to query input configurations: {}", err))?
+        .filter(|range| options.channels.is_none() || options.channels == Some(range.channels()))
+        .collect();
+    // On prefere le f32 pour eviter une conversion
+    ranges.sort_by_key(|range| range.sample_format() != SampleFormat::F32);
+
+    let config = match sample_rate {
+        Some(rate) => ranges
+            .into_iter()
+            .find_map(|range| range.try_with_sample_rate(SampleRate(rate))),
+        None => ranges
+            .into_iter()
+            .next()
+            .map(|range| range.with_max_sample_rate()),
+    };
+    config.ok_or_else(|| {
+        format!(
+            "Input device supports no configuration with channels={} sample_rate={} (run with list-devices)",
+            options.channels.map_or(String::from("any"), |c| c.to_string()),
+            sample_rate.map_or(String::from("any"), |r| r.to_string())
+        )
+    })
+}
+
+// Builds an input stream for any sample format and hands the callback
+// interleaved f32 samples, one value per channel and frame.
+pub fn build_input_stream<D, E>(
+    device: &Device,
+    config: &SupportedStreamConfig,
+    data_callback: D,
+    error_callback: E,
+) -> Result<Stream, String>
+where
+    D: FnMut(&[f32], &InputCallbackInfo) + Send + 'static,
+    E: FnMut(StreamError) + Send + 'static,
+{
+    let stream_config = config.config();
+    let stream = match config.sample_format() {
+        SampleFormat::I8 => {
+            build::<i8, D, E>(device, &stream_config, data_callback, error_callback)
+        }
+        SampleFormat::I16 => {
+            build::<i16, D, E>(device, &stream_config, data_callback, error_callback)
+        }
+        SampleFormat::I32 => {
+            build::<i32, D, E>(device, &stream_config, data_callback, error_callback)
+        }
+        SampleFormat::I64 => {
+            build::<i64, D, E>(device, &stream_config, data_callback, error_callback)
+        }
+        SampleFormat::U8 => {
+            build::<u8, D, E>(device, &stream_config, data_callback, error_callback)
+        }
+        SampleFormat::U16 => {
+            build::<u16, D, E>(device, &stream_config, data_callback, error_callback)
+        }
+        SampleFormat::U32 => {
+            build::<u32, D, E>(device, &stream_config, data_callback, error_callback)
+        }
+        SampleFormat::U64 => {
+            build::<u64, D, E>(device, &stream_config, data_callback, error_callback)
+        }
+        SampleFormat::F32 => {
+            build::<f32, D, E>(device, &stream_config, data_callback, error_callback)
+        }
+        SampleFormat::F64 => {
+            build::<f64, D, E>(device, &stream_config, data_callback, error_callback)
+        }
+        other => return Err(format!("Unsupported sample format {:?}", other)),
+    };
+    stream.map_err(|err| format!("Failed to build input stream: {}", err))
+}
+
+fn build<T, D, E>(
+    device: &Device,
+    config: &StreamConfig,
+    mut data_callback: D,
+    error_callback: E,
+) -> Result<Stream, cpal::BuildStreamError>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+    D: FnMut(&[f32], &InputCallbackInfo) + Send + 'static,
+    E: FnMut(StreamError) + Send + 'static,
+{
+    let mut converted: Vec<f32> = Vec::with_capacity(BUFFER_CAPACITY);
+    let block = chunk_size(config.channels);
+    device.build_input_stream(
+        config,
+        move |data: &[T], info: &InputCallbackInfo| {
+            for chunk in data.chunks(block) {
+                converted.clear();
+                converted.extend(chunk.iter().map(|&s| s.to_sample::<f32>()));
+                data_callback(&converted, info);
+            }
+        },
+        error_callback,
+        None,
+    )
+}
+
+// Builds an output stream for any sample format; the callback fills
+// interleaved f32 samples in [-1, 1].
+pub fn build_output_stream<D, E>(
+    device: &Device,
+    config: &SupportedStreamConfig,
+    data_callback: D,
+    error_callback: E,
+) -> Result<Stream, String>
+where
+    D: FnMut(&mut [f32]) + Send + 'static,
+    E: FnMut(StreamError) + Send + 'static,
+{
+    let stream_config = config.config();
+    let stream = match config.sample_format() {
+        SampleFormat::I8 => {
+            build_output::<i8, D, E>(device, &stream_config, data_callback, error_callback)
+        }
+        SampleFormat::I16 => {
+            build_output::<i16, D, E>(device, &stream_config, data_callback, error_callback)
+        }
+        SampleFormat::I32 => {
+            build_output::<i32, D, E>(device, &stream_config, data_callback, error_callback)
+        }
+        SampleFormat::I64 => {
+            build_output::<i64, D, E>(device, &stream_config, data_callback, error_callback)
+        }
+        SampleFormat::U8 => {
+            build_output::<u8, D, E>(device, &stream_config, data_callback, error_callback)
+        }
+        SampleFormat::U16 => {
+            build_output::<u16, D, E>(device, &stream_config, data_callback, error_callback)
+        }
+        SampleFormat::U32 => {
+            build_output::<u32, D, E>(device, &stream_config, data_callback, error_callback)
+        }
+        SampleFormat::U64 => {
+            build_output::<u64, D, E>(device, &stream_config, data_callback, error_callback)
+        }
+        SampleFormat::F32 => {
+            build_output::<f32, D, E>(device, &stream_config, data_callback, error_callback)
+        }
+        SampleFormat::F64 => {
+            build_output::<f64, D, E>(device, &stream_config, data_callback, error_callback)
+        }
+        other => return Err(format!("Unsupported sample format {:?}", other)),
+    };
+    stream.map_err(|err| format!("Failed to build output stream: {}", err))
+}
+
+fn build_output<T, D, E>(
+    device: &Device,
+    config: &StreamConfig,
+    mut data_callback: D,
+    error_callback: E,
+) -> Result<Stream, cpal::BuildStreamError>
+where
+    T: SizedSample + FromSample<f32>,
+    D: FnMut(&mut [f32]) + Send + 'static,
+    E: FnMut(StreamError) + Send + 'static,
+{
+    let mut generated: Vec<f32> = Vec::with_capacity(BUFFER_CAPACITY);
+    let block = chunk_size(config.channels);
+    device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &OutputCallbackInfo| {
+            for chunk in data.chunks_mut(block) {
+                generated.clear();
+                generated.resize(chunk.len(), 0.0);
+                data_callback(&mut generated);
+                for (out, &sample) in chunk.iter_mut().zip(&generated) {
+                    *out = sample.to_sample::<T>();
+                }
+            }
+        },
+        error_callback,
+        None,
+    )
+}
+
+// Largest number of samples that fits in the conversion buffers and holds whole frames.
+fn chunk_size(channels: u16) -> usize {
+    let channels = (channels as usize).clamp(1, BUFFER_CAPACITY);
+    BUFFER_CAPACITY / channels * channels
+}
+
+// Index of the device called `wanted`, or else of the first one whose name
+// contains it, ignoring case.
+fn match_name(names: &[String], wanted: &str) -> Option<usize> {
+    let wanted_lower = wanted.to_lowercase();
+    names.iter().position(|name| name == wanted).or_else(|| {
+        names
+            .iter()
+            .position(|name| name.to_lowercase().contains(&wanted_lower))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_device_names() {
+        let names: Vec<String> = ["USB Audio Device", "USB Audio", "Built-in Microphone"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        // Le nom exact passe avant une correspondance partielle plus haut dans la liste
+        assert_eq!(match_name(&names, "USB Audio"), Some(1));
+        assert_eq!(match_name(&names, "usb"), Some(0));
+        assert_eq!(match_name(&names, "MICRO"), Some(2));
+        assert_eq!(match_name(&names, "HDMI"), None);
+    }
+
+    #[test]
+    fn chunks_hold_whole_frames() {
+        assert_eq!(chunk_size(1), BUFFER_CAPACITY);
+        assert_eq!(chunk_size(2), BUFFER_CAPACITY);
+        assert_eq!(chunk_size(6), 16380);
+        assert_eq!(chunk_size(0), BUFFER_CAPACITY);
+    }
+}
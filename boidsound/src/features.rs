@@ -0,0 +1,317 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+// Part de l'energie sous la frequence de rolloff
+const ROLLOFF_RATIO: f32 = 0.85;
+// Lissage applique aux valeurs avant de piloter les boids
+const SMOOTHING: f32 = 0.2;
+
+// Spectral features of one analysis window, each scaled to 0..1.
+#[derive(Clone, Copy, Default)]
+pub struct Features {
+    pub centroid: f32,
+    pub rolloff: f32,
+    pub flatness: f32,
+    pub flux: f32,
+}
+
+impl Features {
+    fn get(&self, feature: Feature) -> f32 {
+        match feature {
+            Feature::Centroid => self.centroid,
+            Feature::Rolloff => self.rolloff,
+            Feature::Flatness => self.flatness,
+            Feature::Flux => self.flux,
+        }
+    }
+}
+
+// Keeps the previous spectrum to compute the flux.
+#[derive(Default)]
+pub struct FeatureExtractor {
+    previous: Vec<f32>,
+}
+
+impl FeatureExtractor {
+    // `magnitudes` covers the bins from DC up to Nyquist.
+    pub fn extract(&mut self, magnitudes: &[f32]) -> Features {
+        let bins = magnitudes.len().max(1) as f32;
+        let total: f32 = magnitudes.iter().sum();
+        if total <= 0.0 {
+            self.previous.clear();
+            return Features::default();
+        }
+
+        let centroid = magnitudes
+            .iter()
+            .enumerate()
+            .map(|(i, &m)| i as f32 * m)
+            .sum::<f32>()
+            / total
+            / bins;
+
+        let mut cumulated = 0.0;
+        let rolloff_bin = magnitudes
+            .iter()
+            .position(|&m| {
+                cumulated += m;
+                cumulated >= ROLLOFF_RATIO * total
+            })
+            .unwrap_or(magnitudes.len());
+        let rolloff = rolloff_bin as f32 / bins;
+
+        // Moyenne geometrique sur moyenne arithmetique
+        let log_mean = magnitudes.iter().map(|&m| (m + 1e-12).ln()).sum::<f32>() / bins;
+        let flatness = (log_mean.exp() / (total / bins)).clamp(0.0, 1.0);
+
+        let flux = if self.previous.len() == magnitudes.len() {
+            let rise: f32 = magnitudes
+                .iter()
+                .zip(&self.previous)
+                .map(|(&m, &p)| (m - p).max(0.0))
+                .sum();
+            (rise / total).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        self.previous.clear();
+        self.previous.extend_from_slice(magnitudes);
+
+        Features {
+            centroid,
+            rolloff,
+            flatness,
+            flux,
+        }
+    }
+}
+
+// Features published by the analysis thread, stored as bits.
+#[derive(Default)]
+pub struct SharedFeatures([AtomicU32; 4]);
+
+impl SharedFeatures {
+    pub fn store(&self, features: Features) {
+        let values = [
+            features.centroid,
+            features.rolloff,
+            features.flatness,
+            features.flux,
+        ];
+        for (slot, value) in self.0.iter().zip(values) {
+            slot.store(value.to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    pub fn load(&self) -> Features {
+        let value = |i: usize| f32::from_bits(self.0[i].load(Ordering::Relaxed));
+        Features {
+            centroid: value(0),
+            rolloff: value(1),
+            flatness: value(2),
+            flux: value(3),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Feature {
+    Centroid,
+    Rolloff,
+    Flatness,
+    Flux,
+}
+
+#[derive(Clone, Copy)]
+pub enum Param {
+    Color,
+    Speed,
+    Turn,
+    Separation,
+}
+
+// Boid parameters after the mappings were applied.
+pub struct Params {
+    pub color: [f32; 4],
+    pub speed: f64,
+    pub max_angle: f64,
+    pub separation: f64,
+}
+
+// One `map=<feature>:<param>[:<min>:<max>]` line of the config file.
+pub struct Mapping {
+    feature: Feature,
+    param: Param,
+    min: f64,
+    max: f64,
+    value: f32,
+}
+
+impl Mapping {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let parts: Vec<&str> = spec.split(':').collect();
+        let feature = match parts.first().copied() {
+            Some("centroid") => Feature::Centroid,
+            Some("rolloff") => Feature::Rolloff,
+            Some("flatness") => Feature::Flatness,
+            Some("flux") => Feature::Flux,
+            _ => return Err(format!("Invalid map '{}': unknown feature", spec)),
+        };
+        let (param, min, max) = match parts.get(1).copied() {
+            Some("color") => (Param::Color, 0.0, 1.0),
+            Some("speed") => (Param::Speed, 1.0, 8.0),
+            Some("turn") => (Param::Turn, 1.0, 30.0),
+            Some("separation") => (Param::Separation, 2.0, 30.0),
+            _ => return Err(format!("Invalid map '{}': unknown parameter", spec)),
+        };
+        let (min, max) = match parts[2..] {
+            [] => (min, max),
+            [low, high] => match (low.parse(), high.parse()) {
+                (Ok(low), Ok(high)) => (low, high),
+                _ => return Err(format!("Invalid map '{}': bad range", spec)),
+            },
+            _ => return Err(format!("Invalid map '{}': expected min and max", spec)),
+        };
+        Ok(Mapping {
+            feature,
+            param,
+            min,
+            max,
+            value: 0.0,
+        })
+    }
+
+    pub fn apply(&mut self, features: &Features, params: &mut Params) {
+        self.value += SMOOTHING * (features.get(self.feature) - self.value);
+        let scaled = self.min + self.value as f64 * (self.max - self.min);
+        match self.param {
+            Param::Color => params.color = hue_to_color(scaled as f32),
+            Param::Speed => params.speed = scaled,
+            Param::Turn => params.max_angle = scaled,
+            Param::Separation => params.separation = scaled,
+        }
+    }
+}
+
+// Couleur saturee pour une teinte entre 0 et 1
+fn hue_to_color(hue: f32) -> [f32; 4] {
+    let h = hue.rem_euclid(1.0) * 6.0;
+    let x = 1.0 - (h % 2.0 - 1.0).abs();
+    let (r, g, b) = match h as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    [r, g, b, 1.0]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::overlay::BandAnalyzer;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    const BLOCK: usize = 512;
+
+    fn spectrum(block: &[f32]) -> Vec<f32> {
+        let mut analyzer = BandAnalyzer::new(BLOCK, 48000.0);
+        analyzer.bands(block);
+        let mut magnitudes = Vec::new();
+        analyzer.magnitudes(&mut magnitudes);
+        magnitudes
+    }
+
+    // Sinus centre sur le bin 10 (937.5 Hz)
+    fn sine() -> Vec<f32> {
+        (0..BLOCK)
+            .map(|i| (std::f32::consts::TAU * 10.0 * i as f32 / BLOCK as f32).sin())
+            .collect()
+    }
+
+    #[test]
+    fn parses_mappings() {
+        let mapping = Mapping::parse("flux:speed:1:10").unwrap();
+        assert!(matches!(mapping.feature, Feature::Flux));
+        assert!(matches!(mapping.param, Param::Speed));
+        assert_eq!((mapping.min, mapping.max), (1.0, 10.0));
+
+        // Sans plage, celle du parametre par defaut
+        let mapping = Mapping::parse("centroid:turn").unwrap();
+        assert!(matches!(mapping.feature, Feature::Centroid));
+        assert!(matches!(mapping.param, Param::Turn));
+        assert_eq!((mapping.min, mapping.max), (1.0, 30.0));
+
+        for spec in [
+            "loudness:speed",
+            "flux:size",
+            "flux",
+            "flux:speed:1",
+            "flux:speed:1:x",
+            "flux:speed:1:2:3",
+        ] {
+            assert!(Mapping::parse(spec).is_err(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn mapping_scales_the_feature_to_its_range() {
+        let mut mapping = Mapping::parse("flatness:separation:4:40").unwrap();
+        let mut params = Params {
+            color: [0.0; 4],
+            speed: 0.0,
+            max_angle: 0.0,
+            separation: 0.0,
+        };
+        let features = Features {
+            flatness: 1.0,
+            ..Features::default()
+        };
+        for _ in 0..100 {
+            mapping.apply(&features, &mut params);
+        }
+        assert!((params.separation - 40.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn flatness_tells_noise_from_a_tone() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let noise: Vec<f32> = (0..BLOCK).map(|_| rng.gen_range(-1.0..=1.0)).collect();
+        let mut extractor = FeatureExtractor::default();
+        let flatness = extractor.extract(&spectrum(&noise)).flatness;
+        assert!(flatness > 0.7, "{}", flatness);
+
+        let mut extractor = FeatureExtractor::default();
+        let flatness = extractor.extract(&spectrum(&sine())).flatness;
+        assert!(flatness < 0.05, "{}", flatness);
+    }
+
+    #[test]
+    fn centroid_rolloff_and_flux_of_a_tone() {
+        let magnitudes = spectrum(&sine());
+        let bins = magnitudes.len() as f32;
+        let mut extractor = FeatureExtractor::default();
+        let features = extractor.extract(&magnitudes);
+        assert!(
+            (features.centroid - 10.0 / bins).abs() < 0.01,
+            "{}",
+            features.centroid
+        );
+        assert!(
+            (features.rolloff - 10.0 / bins).abs() < 0.01,
+            "{}",
+            features.rolloff
+        );
+        // Pas de spectre precedent, puis le meme spectre : pas de flux
+        assert_eq!(features.flux, 0.0);
+        assert_eq!(extractor.extract(&magnitudes).flux, 0.0);
+
+        // Une attaque apres un son faible
+        let quiet: Vec<f32> = magnitudes.iter().map(|m| m * 0.1).collect();
+        extractor.extract(&quiet);
+        let flux = extractor.extract(&magnitudes).flux;
+        assert!((flux - 0.9).abs() < 0.01, "{}", flux);
+    }
+}
@@ -0,0 +1,144 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+pub const DEFAULT_LEVEL: f32 = -18.0;
+// Graine fixe : le meme bruit a chaque lancement
+const SEED: u64 = 0x5eed;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Signal {
+    Sine(f64),
+    // Balayage logarithmique de start a end Hz en `seconds`, puis recommence
+    Sweep { start: f64, end: f64, seconds: f64 },
+    White,
+    Pink,
+    // Une impulsion toutes les `period` secondes
+    Impulse(f64),
+}
+
+impl Signal {
+    // sine:<Hz> | sweep:<start Hz>:<end Hz>:<seconds> | white | pink | impulse[:<period s>]
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let error = || format!("Invalid signal '{}'", spec);
+        let parts: Vec<&str> = spec.split(':').collect();
+        let number = |index: usize, default: Option<f64>| match parts.get(index) {
+            Some(text) => text
+                .parse::<f64>()
+                .ok()
+                .filter(|&value| value > 0.0)
+                .ok_or_else(error),
+            None => default.ok_or_else(error),
+        };
+        let signal = match parts[0] {
+            "sine" => Signal::Sine(number(1, Some(1000.0))?),
+            "sweep" => Signal::Sweep {
+                start: number(1, Some(20.0))?,
+                end: number(2, Some(20000.0))?,
+                seconds: number(3, Some(5.0))?,
+            },
+            "white" => Signal::White,
+            "pink" => Signal::Pink,
+            "impulse" => Signal::Impulse(number(1, Some(1.0))?),
+            _ => return Err(error()),
+        };
+        Ok(signal)
+    }
+}
+
+// Produces a test signal one sample at a time, deterministic for a given
+// signal and sample rate.
+pub struct Generator {
+    signal: Signal,
+    sample_rate: f64,
+    amplitude: f32,
+    position: u64,
+    // Echantillons restant avant la prochaine impulsion
+    until_impulse: u64,
+    rng: StdRng,
+    // Etats des filtres du bruit rose
+    pink: [f32; 7],
+}
+
+impl Generator {
+    // `level` is the peak level in dBFS.
+    pub fn new(signal: Signal, sample_rate: u32, level: f32) -> Self {
+        Generator {
+            signal,
+            sample_rate: sample_rate as f64,
+            amplitude: 10f32.powf(level / 20.0),
+            position: 0,
+            until_impulse: 0,
+            rng: StdRng::seed_from_u64(SEED),
+            pink: [0.0; 7],
+        }
+    }
+
+    pub fn next_sample(&mut self) -> f32 {
+        let t = self.position as f64 / self.sample_rate;
+        let value = match self.signal {
+            Signal::Sine(frequency) => (std::f64::consts::TAU * frequency * t).sin() as f32,
+            Signal::Sweep {
+                start,
+                end,
+                seconds,
+            } => {
+                // Sinus a frequence exponentielle (Farina), phase nulle au depart
+                let rate = (end / start).ln();
+                let t = t % seconds;
+                let phase = std::f64::consts::TAU * start * seconds / rate
+                    * ((t / seconds * rate).exp() - 1.0);
+                phase.sin() as f32
+            }
+            Signal::White => self.rng.gen_range(-1.0..=1.0),
+            Signal::Pink => self.next_pink(),
+            Signal::Impulse(period) => {
+                let value = if self.until_impulse == 0 {
+                    self.until_impulse = (period * self.sample_rate).round().max(1.0) as u64;
+                    1.0
+                } else {
+                    0.0
+                };
+                self.until_impulse -= 1;
+                value
+            }
+        };
+        self.position += 1;
+        value * self.amplitude
+    }
+
+    // Bruit blanc filtre a -3 dB par octave (methode de Paul Kellett)
+    fn next_pink(&mut self) -> f32 {
+        let white: f32 = self.rng.gen_range(-1.0..=1.0);
+        let b = &mut self.pink;
+        b[0] = 0.99886 * b[0] + white * 0.0555179;
+        b[1] = 0.99332 * b[1] + white * 0.0750759;
+        b[2] = 0.96900 * b[2] + white * 0.153852;
+        b[3] = 0.86650 * b[3] + white * 0.3104856;
+        b[4] = 0.55000 * b[4] + white * 0.5329522;
+        b[5] = -0.7616 * b[5] - white * 0.0168980;
+        let pink = b[..6].iter().sum::<f32>() + b[6] + white * 0.5362;
+        b[6] = white * 0.115926;
+        (pink * 0.2).clamp(-1.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_signals() {
+        assert_eq!(Signal::parse("sine:440"), Ok(Signal::Sine(440.0)));
+        assert_eq!(
+            Signal::parse("sweep:100:1000:2"),
+            Ok(Signal::Sweep {
+                start: 100.0,
+                end: 1000.0,
+                seconds: 2.0
+            })
+        );
+        assert_eq!(Signal::parse("impulse"), Ok(Signal::Impulse(1.0)));
+        assert!(Signal::parse("square").is_err());
+        assert!(Signal::parse("sine:-5").is_err());
+    }
+}
@@ -2,20 +2,42 @@ extern crate cpal;
 extern crate piston_window;
 extern crate rand;
 
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+mod calibrate;
+mod device;
+mod features;
+mod generator;
+mod midi;
+mod osc;
+mod overlay;
+mod pipeline;
+mod session;
+mod source;
+mod stereo;
+
+use calibrate::{Calibration, CONFIG_FILE};
+use features::{FeatureExtractor, Mapping, SharedFeatures};
+use pipeline::SharedTarget;
 use piston_window::*;
 use rand::Rng;
+use source::AudioSource;
 use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
+use stereo::{StereoMode, StereoSplitter};
 
 const WIDTH: f64 = 800.0;
 const HEIGHT: f64 = 600.0;
 const BOID_DISTANCE: f64 = 8.0;
 const BOID_SAULT: f64 = 1.9;
 const BOID_SIZE: f64 = 12.0;
+const BOID_COLOR: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
 const HEIGHT_OBS: f64 = 140.0;
 const WIDTH_OBS: f64 = 140.0;
+const DEFAULT_THRESHOLDS: [f32; 3] = [0.000200, 0.000400, 0.000600];
+const DEFAULT_CALIBRATION_SECS: u64 = 5;
+const BLOCK_SIZE: usize = 512;
 
 #[derive(Clone)]
 struct Boid {
@@ -36,12 +58,12 @@ struct Obstacle {
 impl Boid {
     pub fn update(
         &mut self,
-        target_x: f64,
-        target_y: f64,
+        (target_x, target_y): (f64, f64),
         boids: &[Boid],
         obstacles: &[Obstacle],
         max_angle: f64,
         boid_speed: f64,
+        separation: f64,
     ) {
         let ix = self.x;
         let iy = self.y;
@@ -72,7 +94,7 @@ impl Boid {
         }
 
         for boid in boids {
-            if (self.x - boid.x).abs() < BOID_DISTANCE || (self.y - boid.y).abs() < BOID_DISTANCE {
+            if (self.x - boid.x).abs() < separation || (self.y - boid.y).abs() < separation {
                 let mut rng = rand::thread_rng();
                 self.x += rng.gen_range(BOID_SAULT * -1.0..BOID_SAULT);
                 self.y += rng.gen_range(BOID_SAULT * -1.0..BOID_SAULT);
@@ -99,9 +121,9 @@ impl Boid {
         self.y = rng.gen_range(0.0..HEIGHT);
     }
 
-    fn draw(&self, c: Context, g: &mut G2d) {
+    fn draw(&self, color: [f32; 4], c: Context, g: &mut G2d) {
         ellipse(
-            [1.0, 0.0, 0.0, 1.0],
+            color,
             [self.x, self.y, BOID_SIZE, BOID_SIZE],
             c.transform,
             g,
@@ -128,8 +150,38 @@ impl Obstacle {
     }
 }
 
+// Applies and saves the thresholds once the calibration window is over.
+fn finish_calibration(calibration: &Mutex<Option<Calibration>>, freq_thresholds: &Mutex<[f32; 3]>) {
+    let mut calibration = calibration.lock().unwrap();
+    let thresholds = match calibration.as_ref() {
+        Some(c) if c.is_done() => c.thresholds(),
+        _ => return,
+    };
+    if let Some((floor, peak)) = calibration.as_ref().and_then(|c| c.noise_floor_and_peak()) {
+        println!("Noise floor: {:.5}, peak: {:.5}", floor, peak);
+    }
+    *calibration = None;
+
+    match thresholds {
+        Some(thresholds) => {
+            *freq_thresholds.lock().unwrap() = thresholds;
+            println!(
+                "Calibrated freq_thresholds={}",
+                calibrate::format_thresholds(&thresholds)
+            );
+            match calibrate::save_thresholds(CONFIG_FILE, &thresholds) {
+                Ok(()) => println!("Saved to {}", CONFIG_FILE),
+                Err(err) => eprintln!("Failed to save {}: {}", CONFIG_FILE, err),
+            }
+        }
+        None => eprintln!("Calibration failed: no audio level measured, keeping thresholds"),
+    }
+}
+
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    // Les options de la ligne de commande passent avant celles du fichier de config
+    let mut args: Vec<String> = env::args().collect();
+    args.extend(calibrate::load_config(CONFIG_FILE));
 
     if args.contains(&String::from("help")) {
         println!("Usage: cargo run -- [OPTIONS]");
@@ -139,9 +191,109 @@ fn main() {
         println!("  num_obs=<number>            Number of obstacles (default: 30)");
         println!("  max_angle=<angle>           Maximum angle for boid rotation (default: 30.0)");
         println!("  freq_thresholds=<200,400,600> Frequency thresholds for sound detection (default: 200,400,600)");
+        println!("  calibrate=<seconds>         Measure the room noise at startup to set freq_thresholds");
+        println!("  device=<name>               Input device, exact name or part of it (default: system default)");
+        println!("  channels=<number>           Number of input channels to open (default: device default)");
+        println!("  sample_rate=<hz>            Input sample rate (default: device default)");
+        println!("  source=<source>             device (default), wav:<path>, synth:<signal>[@<s>][,...] or null");
+        println!("  level=<dBFS>                Peak level of the synth source (default: -18)");
+        println!(
+            "  list-devices                List the input devices and their formats, then exit"
+        );
+        println!("  stereo=<mode>               level or time: the left/right balance of a stereo input moves the target (default: off)");
+        println!("  map=<feature>:<param>[:<min>:<max>]  Drive a boid parameter from a spectral feature, repeatable");
+        println!("                              features: centroid, rolloff, flatness, flux");
+        println!("                              params: color (0..1), speed (1..8), turn (1..30), separation (2..30)");
+        println!("  record=<name>               Record the audio to <name>.wav and the boids to <name>.log");
+        println!(
+            "  replay=<name>               Replay a recorded session, with its audio on the default output"
+        );
+        println!(
+            "  output=<name>               Output device for replay=, exact name or part of it"
+        );
+        println!("  midi=<file.mid>             Drive the boids from a MIDI file instead of the input (with record=, only the .log is written)");
+        println!("  osc_port=<port>             Listen for OSC messages on 127.0.0.1:<port>:");
+        println!("                                /boids/target x y, /boids/speed v, /boids/max_angle v,");
+        println!("                                /boids/thresholds a b c, /boids/randomize");
+        println!("Keys:");
+        println!(
+            "  C                           Recalibrate freq_thresholds (default: {} seconds)",
+            DEFAULT_CALIBRATION_SECS
+        );
+        println!("  O                           Show the spectrogram and threshold overlay");
+        println!("  any other key               Randomize boid positions");
+        println!(
+            "Settings can also be stored as key=value lines in {}",
+            CONFIG_FILE
+        );
+        return;
+    }
+
+    if args.contains(&String::from("list-devices")) {
+        device::list_devices();
         return;
     }
 
+    if let Some(name) = args
+        .iter()
+        .find(|arg| arg.starts_with("replay="))
+        .and_then(|arg| arg.split('=').nth(1))
+    {
+        let output = args
+            .iter()
+            .find(|arg| arg.starts_with("output="))
+            .and_then(|arg| arg.split('=').nth(1));
+        if let Err(err) = session::replay(name, output) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let mut midi_player = args
+        .iter()
+        .find(|arg| arg.starts_with("midi="))
+        .and_then(|arg| arg.split('=').nth(1))
+        .map(|path| {
+            let player = midi::MidiPlayer::load(path, WIDTH, HEIGHT).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            });
+            println!(
+                "Playing {} ({:.1} s, {} channels)",
+                path,
+                player.duration(),
+                player.channel_count()
+            );
+            player
+        });
+
+    let osc_commands = args
+        .iter()
+        .find(|arg| arg.starts_with("osc_port="))
+        .and_then(|arg| arg.split('=').nth(1))
+        .and_then(|val| val.parse().ok())
+        .map(|port| {
+            let (commands, port) = osc::spawn_server(port).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            });
+            println!("Listening for OSC messages on 127.0.0.1:{}", port);
+            commands
+        });
+
+    let stereo_mode = StereoMode::from_arg(
+        args.iter()
+            .find(|arg| arg.starts_with("stereo="))
+            .and_then(|arg| arg.split('=').nth(1)),
+    );
+
+    let record: Option<String> = args
+        .iter()
+        .find(|arg| arg.starts_with("record="))
+        .and_then(|arg| arg.split('=').nth(1))
+        .map(String::from);
+
     let num_boids = args
         .iter()
         .find(|arg| arg.starts_with("num_boids="))
@@ -149,7 +301,7 @@ fn main() {
         .and_then(|val| val.parse().ok())
         .unwrap_or(30);
 
-    let boid_speed = args
+    let mut boid_speed = args
         .iter()
         .find(|arg| arg.starts_with("boid_speed="))
         .and_then(|arg| arg.split('=').nth(1))
@@ -163,7 +315,7 @@ fn main() {
         .and_then(|val| val.parse().ok())
         .unwrap_or(30);
 
-    let max_angle = args
+    let mut max_angle = args
         .iter()
         .find(|arg| arg.starts_with("max_angle="))
         .and_then(|arg| arg.split('=').nth(1))
@@ -176,56 +328,192 @@ fn main() {
         .and_then(|arg| arg.split('=').nth(1))
         .map(|val| {
             let parts: Vec<f32> = val.split(',').filter_map(|s| s.parse().ok()).collect();
-            if parts.len() == 3 {
-                parts
-            } else {
-                vec![0.000200, 0.000400, 0.000600]
+            match <[f32; 3]>::try_from(parts) {
+                Ok(thresholds) => thresholds,
+                Err(_) => {
+                    eprintln!(
+                        "Invalid freq_thresholds '{}', expected three numbers: using defaults",
+                        val
+                    );
+                    DEFAULT_THRESHOLDS
+                }
             }
         })
-        .unwrap_or(vec![0.000200, 0.000400, 0.000600]);
+        .unwrap_or(DEFAULT_THRESHOLDS);
+
+    let calibrate_secs: Option<u64> = args
+        .iter()
+        .find(|arg| arg.starts_with("calibrate="))
+        .and_then(|arg| arg.split('=').nth(1))
+        .and_then(|val| val.parse().ok());
 
-    let target = Arc::new(Mutex::new((WIDTH / 2.0, HEIGHT / 2.0)));
+    let freq_thresholds = Arc::new(Mutex::new(freq_thresholds));
+    let freq_thresholds_clone = Arc::clone(&freq_thresholds);
+
+    let calibration: Arc<Mutex<Option<Calibration>>> = Arc::new(Mutex::new(None));
+    let calibration_clone = Arc::clone(&calibration);
+    if let Some(secs) = calibrate_secs {
+        println!("Calibrating for {} seconds, make some noise...", secs);
+        *calibration.lock().unwrap() = Some(Calibration::new(Duration::from_secs(secs)));
+    }
+
+    let target = Arc::new(SharedTarget::new((WIDTH / 2.0, HEIGHT / 2.0)));
     let target_clone = Arc::clone(&target);
 
-    thread::spawn(move || {
-        let host = cpal::default_host();
-        let device = host
-            .default_input_device()
-            .expect("No input device available");
-        let config = device
-            .default_input_config()
-            .expect("Failed to get default input format")
-            .config();
-
-        let stream = device
-            .build_input_stream(
-                &config,
-                move |data: &[f32], _: &cpal::InputCallbackInfo| {
-                    let avg_freq = data.iter().map(|&x| x.abs()).sum::<f32>() / data.len() as f32;
-                    let mut target = target_clone.lock().unwrap();
+    let mut mappings: Vec<Mapping> = args
+        .iter()
+        .filter(|arg| arg.starts_with("map="))
+        .filter_map(|arg| arg.split('=').nth(1))
+        .filter_map(|spec| {
+            Mapping::parse(spec)
+                .map_err(|err| eprintln!("{}", err))
+                .ok()
+        })
+        .collect();
+    let features = Arc::new(SharedFeatures::default());
+    let features_clone = Arc::clone(&features);
+
+    let history = Arc::new(Mutex::new(overlay::History::default()));
+    let history_clone = Arc::clone(&history);
+    let mut show_overlay = false;
+
+    // Le wav et le log partagent la meme origine des temps
+    let session_start = Instant::now();
+    let running = Arc::new(AtomicBool::new(true));
+    let running_clone = Arc::clone(&running);
+
+    // Avec un fichier MIDI, le micro n'est pas utilise
+    let audio_thread = if midi_player.is_some() {
+        None
+    } else {
+        let level = args
+            .iter()
+            .find(|arg| arg.starts_with("level="))
+            .and_then(|arg| arg.split('=').nth(1))
+            .and_then(|val| val.parse().ok())
+            .unwrap_or(generator::DEFAULT_LEVEL);
+        let source = AudioSource::from_args(&args, level).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        });
+        println!("Input: {}", source.describe());
+
+        // Le wav est cree ici pour qu'un chemin invalide arrete le programme avant la fenetre
+        let mut wav = record.as_deref().map(session::wav_path).map(|path| {
+            let spec = hound::WavSpec {
+                channels: source.channels(),
+                sample_rate: source.sample_rate(),
+                bits_per_sample: 32,
+                sample_format: hound::SampleFormat::Float,
+            };
+            match hound::WavWriter::create(&path, spec) {
+                Ok(writer) => (path, writer),
+                Err(err) => {
+                    eprintln!("Failed to create {}: {}", path, err);
+                    std::process::exit(1);
+                }
+            }
+        });
+        if stereo_mode != StereoMode::Off && source.channels() < 2 {
+            eprintln!("stereo mode needs a stereo input, try channels=2");
+        }
+
+        Some(thread::spawn(move || {
+            let sample_rate = source.sample_rate();
+            let channels = source.channels() as usize;
+            let mut wav_started = false;
+
+            // Le callback ne fait que copier les echantillons, l'analyse tourne sur ce thread
+            let (mut sender, mut reader, stats) = pipeline::sample_channel(
+                sample_rate as usize * channels,
+                BLOCK_SIZE * channels,
+                channels,
+                sample_rate,
+            );
+
+            let _running = source
+                .start(move |data, info| {
+                    sender.push(data, info);
+                })
+                .unwrap_or_else(|err| {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                });
+            let mut band_analyzer = overlay::BandAnalyzer::new(BLOCK_SIZE, sample_rate as f32);
+            let mut splitter = StereoSplitter::new(channels, BLOCK_SIZE);
+            let mut extractor = FeatureExtractor::default();
+            let mut magnitudes = Vec::with_capacity(BLOCK_SIZE / 2 + 1);
+            let mut balance = 0.0;
+            let mut reported = (0, 0);
+            while running_clone.load(Ordering::Relaxed) {
+                while let Some(interleaved) = reader.next_frame() {
+                    if let Some((path, writer)) = wav.as_mut() {
+                        // Silence pour le temps ecoule avant le premier bloc
+                        let mut lead = 0;
+                        if !wav_started {
+                            let seconds = session_start.elapsed().as_secs_f64()
+                                - BLOCK_SIZE as f64 / sample_rate as f64;
+                            lead = (seconds.max(0.0) * sample_rate as f64) as usize * channels;
+                            wav_started = true;
+                        }
+                        let written = std::iter::repeat_n(0.0, lead)
+                            .chain(interleaved.iter().copied())
+                            .try_for_each(|sample| writer.write_sample(sample));
+                        // Le disque plein arrete l'enregistrement, pas l'analyse
+                        if let Err(err) = written {
+                            eprintln!("Failed to write {}: {}, recording stopped", path, err);
+                            wav = None;
+                        }
+                    }
+                    splitter.split(interleaved);
+                    let block = &splitter.mono;
+                    let avg_freq = block.iter().map(|&x| x.abs()).sum::<f32>() / block.len() as f32;
+                    history_clone.lock().unwrap().push(overlay::Column {
+                        bands: band_analyzer.bands(block),
+                        level: avg_freq,
+                    });
+                    band_analyzer.magnitudes(&mut magnitudes);
+                    features_clone.store(extractor.extract(&magnitudes));
+                    if let Some(calibration) = calibration_clone.lock().unwrap().as_mut() {
+                        calibration.push(avg_freq);
+                        continue;
+                    }
+                    let freq_thresholds = *freq_thresholds_clone.lock().unwrap();
                     //println!("{} {:?}", avg_freq, freq_thresholds);
-                    if avg_freq > freq_thresholds[2] {
-                        *target = (WIDTH / 2.0, 50.0); // Move up
+                    if stereo_mode != StereoMode::Off {
+                        // En stereo, la balance donne x et le niveau donne y
+                        if avg_freq <= freq_thresholds[0] {
+                            continue;
+                        }
+                        balance = 0.8 * balance + 0.2 * splitter.balance(stereo_mode, sample_rate);
+                        let x = WIDTH / 2.0 + balance as f64 * (WIDTH / 2.0 - 50.0);
+                        if avg_freq > freq_thresholds[2] {
+                            target_clone.store((x, 50.0)); // Move up
+                        } else if avg_freq > freq_thresholds[1] {
+                            target_clone.store((x, HEIGHT - 50.0)); // Move down
+                        } else {
+                            target_clone.store((x, HEIGHT / 2.0));
+                        }
+                    } else if avg_freq > freq_thresholds[2] {
+                        target_clone.store((WIDTH / 2.0, 50.0)); // Move up
                     } else if avg_freq > freq_thresholds[1] {
-                        *target = (WIDTH / 2.0, HEIGHT - 50.0); // Move down
+                        target_clone.store((WIDTH / 2.0, HEIGHT - 50.0)); // Move down
                     } else if avg_freq > freq_thresholds[0] {
-                        *target = (WIDTH - 50.0, HEIGHT / 2.0); // Move right
+                        target_clone.store((WIDTH - 50.0, HEIGHT / 2.0)); // Move right
                     } else {
-                        *target = (50.0, HEIGHT / 2.0); // Move left
+                        target_clone.store((50.0, HEIGHT / 2.0)); // Move left
                     }
-                },
-                move |err| {
-                    eprintln!("Error occurred on stream: {}", err);
-                },
-                None,
-            )
-            .expect("Failed to build input stream");
-
-        stream.play().expect("Failed to play stream");
-        loop {
-            std::thread::sleep(std::time::Duration::from_millis(200));
-        }
-    });
+                }
+                stats.report(&mut reported);
+                std::thread::sleep(std::time::Duration::from_millis(5));
+            }
+            if let Some((path, writer)) = wav {
+                if let Err(err) = writer.finalize() {
+                    eprintln!("Failed to write {}: {}", path, err);
+                }
+            }
+        }))
+    };
 
     let mut window: PistonWindow =
         WindowSettings::new("Boids under sound influence", [WIDTH as u32, HEIGHT as u32])
@@ -254,30 +542,125 @@ fn main() {
 
     println!("Boids and Obstacles created");
 
+    let mut recorder = record.as_deref().map(|name| {
+        // Avec un fichier MIDI il n'y a pas de thread audio, donc pas de wav
+        if midi_player.is_some() {
+            eprintln!(
+                "midi= plays without audio input: only {} is recorded",
+                session::log_path(name)
+            );
+        } else {
+            println!(
+                "Recording to {} and {}",
+                session::wav_path(name),
+                session::log_path(name)
+            );
+        }
+        session::Recorder::create(name, &obstacles).unwrap_or_else(|err| {
+            eprintln!("Failed to create {}: {}", session::log_path(name), err);
+            std::process::exit(1);
+        })
+    });
+
     while let Some(event) = window.next() {
         if let Some(mouse_pos) = event.mouse_cursor_args() {
-            let mut target = target.lock().unwrap();
-            *target = (mouse_pos[0], mouse_pos[1]);
+            target.store((mouse_pos[0], mouse_pos[1]));
         }
 
-        let target = target.lock().unwrap();
+        if let Some(player) = midi_player.as_mut() {
+            player.advance();
+        }
+
+        for command in osc_commands.iter().flat_map(|commands| commands.try_iter()) {
+            match command {
+                osc::Command::Target(x, y) => target.store((x, y)),
+                osc::Command::Speed(speed) => boid_speed = speed,
+                osc::Command::MaxAngle(angle) => max_angle = angle,
+                osc::Command::Thresholds(thresholds) => {
+                    *freq_thresholds.lock().unwrap() = thresholds
+                }
+                osc::Command::Randomize => {
+                    for boid in &mut boids {
+                        boid.randomize();
+                    }
+                }
+            }
+        }
+
+        // Les correspondances du fichier de config remplacent les valeurs par defaut
+        let mut params = features::Params {
+            color: BOID_COLOR,
+            speed: boid_speed,
+            max_angle,
+            separation: BOID_DISTANCE,
+        };
+        if !mappings.is_empty() {
+            let features = features.load();
+            for mapping in &mut mappings {
+                mapping.apply(&features, &mut params);
+            }
+        }
+
+        let target = target.load();
         let obstacles_copy = obstacles.clone();
         for i in 0..boids.len() {
+            // Chaque boid suit l'attracteur de son canal MIDI, sinon la cible du son
+            let (target_x, target_y, speed) = match &midi_player {
+                Some(player) => {
+                    let attractor = player.attractor_for(i);
+                    (
+                        attractor.x,
+                        attractor.y,
+                        params.speed * attractor.speed_scale,
+                    )
+                }
+                None => (target.0, target.1, params.speed),
+            };
             let (left, right) = boids.split_at_mut(i);
             let boid = &mut right[0];
             boid.update(
-                target.0,
-                target.1,
+                (target_x, target_y),
                 left,
                 &obstacles_copy,
-                max_angle,
-                boid_speed,
+                params.max_angle,
+                speed,
+                params.separation,
             );
         }
 
-        if let Some(_button) = event.press_args() {
-            for boid in &mut boids {
-                boid.randomize();
+        if let Some(button) = event.press_args() {
+            if button == Button::Keyboard(Key::O) {
+                show_overlay = !show_overlay;
+            } else if button == Button::Keyboard(Key::C) {
+                println!(
+                    "Calibrating for {} seconds, make some noise...",
+                    DEFAULT_CALIBRATION_SECS
+                );
+                *calibration.lock().unwrap() = Some(Calibration::new(Duration::from_secs(
+                    DEFAULT_CALIBRATION_SECS,
+                )));
+            } else {
+                for boid in &mut boids {
+                    boid.randomize();
+                }
+            }
+        }
+
+        finish_calibration(&calibration, &freq_thresholds);
+
+        if let (Some(log), Some(_)) = (recorder.as_mut(), event.render_args()) {
+            let written = log.write_frame(
+                session_start.elapsed().as_secs_f64(),
+                target,
+                params.color,
+                &boids,
+            );
+            if let Err(err) = written {
+                eprintln!(
+                    "Failed to write the session log: {}, recording stopped",
+                    err
+                );
+                recorder = None;
             }
         }
 
@@ -287,8 +670,20 @@ fn main() {
                 obs.draw(c, g);
             }
             for boid in &boids {
-                boid.draw(c, g);
+                boid.draw(params.color, c, g);
+            }
+            if show_overlay {
+                let thresholds = *freq_thresholds.lock().unwrap();
+                overlay::draw(&history.lock().unwrap(), &thresholds, WIDTH, HEIGHT, c, g);
             }
         });
     }
+
+    // Laisse le thread audio finaliser le wav
+    running.store(false, Ordering::Relaxed);
+    if let Some(audio_thread) = audio_thread {
+        if audio_thread.join().is_err() {
+            eprintln!("The audio thread stopped with an error");
+        }
+    }
 }
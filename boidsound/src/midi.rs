@@ -0,0 +1,284 @@
+use midly::{MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
+use std::fs;
+use std::time::Instant;
+
+// Tempo par defaut d'un fichier MIDI : 120 bpm
+const DEFAULT_TEMPO: u32 = 500_000;
+const MARGIN: f64 = 50.0;
+
+struct NoteEvent {
+    time: f64,
+    channel: u8,
+    key: u8,
+    velocity: u8,
+}
+
+// One attractor per MIDI channel: its height follows the highest held note
+// and its speed the velocity of the last note played.
+pub struct Attractor {
+    pub x: f64,
+    pub y: f64,
+    pub speed_scale: f64,
+    channel: u8,
+    held: Vec<u8>,
+}
+
+pub struct MidiPlayer {
+    events: Vec<NoteEvent>,
+    next: usize,
+    start: Instant,
+    lowest_key: u8,
+    highest_key: u8,
+    height: f64,
+    attractors: Vec<Attractor>,
+}
+
+impl MidiPlayer {
+    pub fn load(path: &str, width: f64, height: f64) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|err| format!("Failed to read {}: {}", path, err))?;
+        let smf =
+            Smf::parse(&bytes).map_err(|err| format!("Invalid MIDI file {}: {}", path, err))?;
+        let events = note_events(&smf);
+        if events.is_empty() {
+            return Err(format!("No notes in {}", path));
+        }
+        Ok(Self::from_events(events, width, height))
+    }
+
+    // `events` must not be empty.
+    fn from_events(events: Vec<NoteEvent>, width: f64, height: f64) -> Self {
+        let mut channels: Vec<u8> = events.iter().map(|e| e.channel).collect();
+        channels.sort_unstable();
+        channels.dedup();
+        // Les canaux se partagent la largeur de la fenetre
+        let attractors = channels
+            .iter()
+            .enumerate()
+            .map(|(i, &channel)| Attractor {
+                x: width * (i + 1) as f64 / (channels.len() + 1) as f64,
+                y: height / 2.0,
+                speed_scale: 1.0,
+                channel,
+                held: Vec::new(),
+            })
+            .collect();
+
+        MidiPlayer {
+            lowest_key: events.iter().map(|e| e.key).min().unwrap(),
+            highest_key: events.iter().map(|e| e.key).max().unwrap(),
+            events,
+            next: 0,
+            start: Instant::now(),
+            height,
+            attractors,
+        }
+    }
+
+    pub fn channel_count(&self) -> usize {
+        self.attractors.len()
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.events.last().map_or(0.0, |e| e.time)
+    }
+
+    // Applies every note event due since the start of playback.
+    pub fn advance(&mut self) {
+        self.advance_to(self.start.elapsed().as_secs_f64());
+    }
+
+    fn advance_to(&mut self, now: f64) {
+        while let Some(event) = self.events.get(self.next).filter(|e| e.time <= now) {
+            self.next += 1;
+            let Some(attractor) = self
+                .attractors
+                .iter_mut()
+                .find(|a| a.channel == event.channel)
+            else {
+                continue;
+            };
+            attractor.held.retain(|&key| key != event.key);
+            if event.velocity > 0 {
+                attractor.held.push(event.key);
+                attractor.speed_scale = 0.25 + 1.75 * event.velocity as f64 / 127.0;
+            }
+            // Les notes aigues en haut
+            if let Some(&highest) = attractor.held.iter().max() {
+                let range = (self.highest_key - self.lowest_key).max(1) as f64;
+                let position = (highest - self.lowest_key) as f64 / range;
+                attractor.y = MARGIN + (1.0 - position) * (self.height - 2.0 * MARGIN);
+            }
+        }
+    }
+
+    // Boids are dealt to the channels in turn.
+    pub fn attractor_for(&self, boid_index: usize) -> &Attractor {
+        &self.attractors[boid_index % self.attractors.len()]
+    }
+}
+
+// Flattens all tracks into note events timed in seconds, following the tempo map.
+fn note_events(smf: &Smf) -> Vec<NoteEvent> {
+    let mut timed: Vec<(u64, TrackEventKind)> = Vec::new();
+    for track in &smf.tracks {
+        let mut tick = 0u64;
+        for event in track {
+            tick += event.delta.as_int() as u64;
+            timed.push((tick, event.kind));
+        }
+    }
+    // Tri stable : a tick egal, l'ordre des pistes est conserve
+    timed.sort_by_key(|(tick, _)| *tick);
+
+    let mut events = Vec::new();
+    let mut tempo = DEFAULT_TEMPO;
+    let mut last_tick = 0u64;
+    let mut time = 0.0;
+    for (tick, kind) in timed {
+        time += (tick - last_tick) as f64 * seconds_per_tick(smf.header.timing, tempo);
+        last_tick = tick;
+        match kind {
+            TrackEventKind::Meta(MetaMessage::Tempo(micros)) => tempo = micros.as_int(),
+            TrackEventKind::Midi { channel, message } => {
+                let (key, velocity) = match message {
+                    MidiMessage::NoteOn { key, vel } => (key.as_int(), vel.as_int()),
+                    MidiMessage::NoteOff { key, .. } => (key.as_int(), 0),
+                    _ => continue,
+                };
+                events.push(NoteEvent {
+                    time,
+                    channel: channel.as_int(),
+                    key,
+                    velocity,
+                });
+            }
+            _ => {}
+        }
+    }
+    events
+}
+
+fn seconds_per_tick(timing: Timing, tempo: u32) -> f64 {
+    match timing {
+        Timing::Metrical(ticks_per_beat) => {
+            tempo as f64 / 1_000_000.0 / ticks_per_beat.as_int().max(1) as f64
+        }
+        Timing::Timecode(fps, subframes) => 1.0 / (fps.as_f32() as f64 * subframes.max(1) as f64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use midly::num::{u15, u24, u28, u4, u7};
+    use midly::{Format, Fps, Header, TrackEvent};
+
+    fn note_on(delta: u32, channel: u8, key: u8, vel: u8) -> TrackEvent<'static> {
+        TrackEvent {
+            delta: u28::new(delta),
+            kind: TrackEventKind::Midi {
+                channel: u4::new(channel),
+                message: MidiMessage::NoteOn {
+                    key: u7::new(key),
+                    vel: u7::new(vel),
+                },
+            },
+        }
+    }
+
+    fn tempo(delta: u32, micros: u32) -> TrackEvent<'static> {
+        TrackEvent {
+            delta: u28::new(delta),
+            kind: TrackEventKind::Meta(MetaMessage::Tempo(u24::new(micros))),
+        }
+    }
+
+    fn song(timing: Timing) -> Smf<'static> {
+        let mut smf = Smf::new(Header::new(Format::SingleTrack, timing));
+        // 120 bpm, une note au tick 480, passage a 60 bpm, une note 480 ticks plus tard
+        smf.tracks.push(vec![
+            note_on(480, 0, 60, 100),
+            tempo(0, 1_000_000),
+            note_on(480, 0, 62, 100),
+        ]);
+        smf
+    }
+
+    fn times(smf: &Smf) -> Vec<f64> {
+        note_events(smf).iter().map(|e| e.time).collect()
+    }
+
+    #[test]
+    fn tempo_changes_move_note_onsets() {
+        // 480 ticks par noire : 0.5 s a 120 bpm puis 1 s a 60 bpm
+        let onsets = times(&song(Timing::Metrical(u15::new(480))));
+        assert_eq!(onsets.len(), 2);
+        assert!((onsets[0] - 0.5).abs() < 1e-9, "{:?}", onsets);
+        assert!((onsets[1] - 1.5).abs() < 1e-9, "{:?}", onsets);
+
+        // En timecode le tempo ne compte pas : 25 images de 40 sous-images
+        let onsets = times(&song(Timing::Timecode(Fps::Fps25, 40)));
+        assert!((onsets[0] - 0.48).abs() < 1e-9, "{:?}", onsets);
+        assert!((onsets[1] - 0.96).abs() < 1e-9, "{:?}", onsets);
+    }
+
+    #[test]
+    fn notes_drive_the_attractor_of_their_channel() {
+        let mut smf = Smf::new(Header::new(
+            Format::SingleTrack,
+            Timing::Metrical(u15::new(480)),
+        ));
+        smf.tracks.push(vec![
+            note_on(0, 0, 48, 0),
+            note_on(0, 3, 72, 127),
+            note_on(480, 0, 60, 127),
+            note_on(480, 0, 60, 0),
+            note_on(0, 0, 48, 0),
+        ]);
+        let mut player = MidiPlayer::from_events(note_events(&smf), 300.0, 500.0);
+        assert_eq!(player.channel_count(), 2);
+
+        player.advance_to(0.0);
+        // Canal 0 : note off, rien ne change ; canal 3 : note la plus aigue, vitesse maximale
+        assert_eq!(player.attractors[0].speed_scale, 1.0);
+        assert_eq!(player.attractors[0].y, 250.0);
+        assert_eq!(player.attractors[1].speed_scale, 2.0);
+        assert_eq!(player.attractors[1].y, MARGIN);
+        assert_eq!(player.attractors[0].x, 100.0);
+        assert_eq!(player.attractors[1].x, 200.0);
+
+        // Le do central est au milieu de la tessiture 48..72
+        player.advance_to(0.5);
+        assert_eq!(player.attractors[0].y, 250.0);
+        assert_eq!(player.attractors[0].speed_scale, 2.0);
+        assert_eq!(player.attractors[1].y, MARGIN);
+
+        // Une vitesse nulle relache la note sans toucher a la vitesse
+        player.advance_to(1.0);
+        assert_eq!(player.attractors[0].speed_scale, 2.0);
+        assert!(player.attractors[0].held.is_empty());
+
+        // Les boids alternent entre les canaux
+        assert_eq!(player.attractor_for(0).channel, 0);
+        assert_eq!(player.attractor_for(1).channel, 3);
+        assert_eq!(player.attractor_for(2).channel, 0);
+    }
+
+    #[test]
+    fn velocity_sets_the_speed_scale() {
+        let mut smf = Smf::new(Header::new(
+            Format::SingleTrack,
+            Timing::Metrical(u15::new(480)),
+        ));
+        smf.tracks
+            .push(vec![note_on(0, 0, 60, 127), note_on(480, 0, 64, 1)]);
+        let mut player = MidiPlayer::from_events(note_events(&smf), 300.0, 500.0);
+        player.advance_to(0.0);
+        assert_eq!(player.attractors[0].speed_scale, 2.0);
+        player.advance_to(0.5);
+        let expected = 0.25 + 1.75 / 127.0;
+        assert!((player.attractors[0].speed_scale - expected).abs() < 1e-12);
+        // La note la plus aigue tenue est en haut
+        assert_eq!(player.attractors[0].y, MARGIN);
+    }
+}
@@ -0,0 +1,243 @@
+use std::net::UdpSocket;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+// Attente apres une erreur de reception, doublee a chaque erreur consecutive
+const MIN_BACKOFF: Duration = Duration::from_millis(10);
+const MAX_BACKOFF: Duration = Duration::from_secs(1);
+
+// Adresses OSC reconnues :
+//   /boids/target x y          position de la cible (pixels)
+//   /boids/speed v             boid_speed
+//   /boids/max_angle v         max_angle
+//   /boids/thresholds a b c    freq_thresholds
+//   /boids/randomize           replace les boids au hasard
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Target(f64, f64),
+    Speed(f64),
+    MaxAngle(f64),
+    Thresholds([f32; 3]),
+    Randomize,
+}
+
+// Listens on the given local UDP port and forwards decoded commands.
+pub fn spawn_server(port: u16) -> Result<(Receiver<Command>, u16), String> {
+    let socket = UdpSocket::bind(("127.0.0.1", port))
+        .map_err(|err| format!("Failed to open OSC port {}: {}", port, err))?;
+    let port = socket.local_addr().map(|addr| addr.port()).unwrap_or(port);
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || serve(socket, sender));
+    Ok((receiver, port))
+}
+
+fn serve(socket: UdpSocket, sender: Sender<Command>) {
+    let mut buffer = [0u8; 1536];
+    let mut backoff = MIN_BACKOFF;
+    loop {
+        let size = match socket.recv(&mut buffer) {
+            Ok(size) => {
+                backoff = MIN_BACKOFF;
+                size
+            }
+            Err(err) => {
+                // Une erreur persistante ne doit pas faire tourner le thread a vide
+                eprintln!("OSC receive error: {}, retrying in {:?}", err, backoff);
+                thread::sleep(backoff);
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                continue;
+            }
+        };
+        match parse_packet(&buffer[..size]) {
+            Ok(commands) => {
+                for command in commands {
+                    if sender.send(command).is_err() {
+                        return;
+                    }
+                }
+            }
+            Err(err) => eprintln!("Ignored OSC packet: {}", err),
+        }
+    }
+}
+
+enum Arg {
+    Float(f32),
+    Int(i32),
+}
+
+impl Arg {
+    fn as_f64(&self) -> f64 {
+        match self {
+            Arg::Float(v) => *v as f64,
+            Arg::Int(v) => *v as f64,
+        }
+    }
+}
+
+// Decodes a message or a bundle of messages.
+pub fn parse_packet(bytes: &[u8]) -> Result<Vec<Command>, String> {
+    let mut reader = Reader { bytes, pos: 0 };
+    if bytes.starts_with(b"#bundle\0") {
+        reader.pos = 16; // "#bundle" puis le time tag
+        let mut commands = Vec::new();
+        while reader.pos < bytes.len() {
+            let size = u32::try_from(reader.int()?)
+                .map_err(|_| String::from("negative bundle element size"))?;
+            let element = reader.take(size as usize)?;
+            commands.extend(parse_packet(element)?);
+        }
+        return Ok(commands);
+    }
+
+    let address = reader.string()?;
+    let tags = if reader.pos < bytes.len() {
+        reader.string()?
+    } else {
+        String::from(",")
+    };
+    let mut args = Vec::new();
+    for tag in tags.chars().skip(1) {
+        args.push(match tag {
+            'f' => Arg::Float(f32::from_bits(reader.int()? as u32)),
+            'i' => Arg::Int(reader.int()?),
+            other => return Err(format!("unsupported argument type '{}'", other)),
+        });
+    }
+
+    let command = match (address.as_str(), args.as_slice()) {
+        ("/boids/target", [x, y]) => Command::Target(x.as_f64(), y.as_f64()),
+        ("/boids/speed", [v]) => Command::Speed(v.as_f64()),
+        ("/boids/max_angle", [v]) => Command::MaxAngle(v.as_f64()),
+        ("/boids/thresholds", [a, b, c]) => {
+            Command::Thresholds([a.as_f64() as f32, b.as_f64() as f32, c.as_f64() as f32])
+        }
+        ("/boids/randomize", _) => Command::Randomize,
+        _ => {
+            return Err(format!(
+                "unknown address {} with {} arguments",
+                address,
+                args.len()
+            ))
+        }
+    };
+    Ok(vec![command])
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, size: usize) -> Result<&'a [u8], String> {
+        let end = self
+            .pos
+            .checked_add(size)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or_else(|| String::from("truncated packet"))?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn int(&mut self) -> Result<i32, String> {
+        let bytes = self.take(4)?;
+        Ok(i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    // Chaine terminee par un zero, completee a un multiple de 4 octets
+    fn string(&mut self) -> Result<String, String> {
+        let rest = &self.bytes[self.pos..];
+        let len = rest
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| String::from("unterminated string"))?;
+        let text = String::from_utf8_lossy(&rest[..len]).into_owned();
+        self.take((len + 4) & !3)?;
+        Ok(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pad(out: &mut Vec<u8>, text: &str) {
+        out.extend_from_slice(text.as_bytes());
+        out.resize((out.len() + 4) & !3, 0);
+    }
+
+    fn message(address: &str, args: &[f32]) -> Vec<u8> {
+        let mut out = Vec::new();
+        pad(&mut out, address);
+        pad(&mut out, &format!(",{}", "f".repeat(args.len())));
+        for arg in args {
+            out.extend_from_slice(&arg.to_bits().to_be_bytes());
+        }
+        out
+    }
+
+    #[test]
+    fn loopback_messages_become_commands() {
+        let (commands, port) = spawn_server(0).unwrap();
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let send = |packet: Vec<u8>| client.send_to(&packet, ("127.0.0.1", port)).unwrap();
+        let next = || commands.recv_timeout(Duration::from_secs(2)).unwrap();
+
+        send(message("/boids/target", &[120.0, 80.0]));
+        assert_eq!(next(), Command::Target(120.0, 80.0));
+
+        send(message("/boids/speed", &[2.5]));
+        assert_eq!(next(), Command::Speed(2.5));
+
+        send(message("/boids/max_angle", &[12.0]));
+        assert_eq!(next(), Command::MaxAngle(12.0));
+
+        send(message("/boids/thresholds", &[0.001, 0.002, 0.004]));
+        assert_eq!(next(), Command::Thresholds([0.001, 0.002, 0.004]));
+
+        send(message("/boids/randomize", &[]));
+        assert_eq!(next(), Command::Randomize);
+    }
+
+    #[test]
+    fn bundle_and_int_arguments() {
+        let mut inner = Vec::new();
+        pad(&mut inner, "/boids/target");
+        pad(&mut inner, ",ii");
+        inner.extend_from_slice(&10i32.to_be_bytes());
+        inner.extend_from_slice(&20i32.to_be_bytes());
+
+        let mut bundle = Vec::new();
+        pad(&mut bundle, "#bundle");
+        bundle.extend_from_slice(&1u64.to_be_bytes());
+        for element in [inner, message("/boids/speed", &[3.0])] {
+            bundle.extend_from_slice(&(element.len() as i32).to_be_bytes());
+            bundle.extend_from_slice(&element);
+        }
+
+        assert_eq!(
+            parse_packet(&bundle).unwrap(),
+            vec![Command::Target(10.0, 20.0), Command::Speed(3.0)]
+        );
+        assert!(parse_packet(&message("/boids/unknown", &[1.0])).is_err());
+    }
+
+    #[test]
+    fn rejects_bad_bundle_sizes() {
+        let mut bundle = Vec::new();
+        pad(&mut bundle, "#bundle");
+        bundle.extend_from_slice(&1u64.to_be_bytes());
+        // -1 : ne doit pas devenir une taille enorme
+        bundle.extend_from_slice(&0xFFFFFFFFu32.to_be_bytes());
+        bundle.extend_from_slice(&message("/boids/randomize", &[]));
+        assert!(parse_packet(&bundle).is_err());
+
+        // Taille plus grande que le paquet
+        let len = bundle.len();
+        bundle[16..20].copy_from_slice(&(len as u32).to_be_bytes());
+        assert!(parse_packet(&bundle).is_err());
+    }
+}
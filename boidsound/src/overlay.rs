@@ -0,0 +1,229 @@
+use piston_window::*;
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+pub const COLUMNS: usize = 160;
+pub const BANDS: usize = 32;
+const HEIGHT: f64 = 128.0;
+const MIN_FREQ: f32 = 50.0;
+const FLOOR_DB: f32 = -90.0;
+
+// Couleurs des seuils, dans l'ordre droite, bas, haut
+const THRESHOLD_COLORS: [[f32; 4]; 3] = [
+    [0.0, 1.0, 0.0, 1.0],
+    [1.0, 1.0, 0.0, 1.0],
+    [1.0, 0.3, 0.3, 1.0],
+];
+
+// One spectrogram column plus the level that was compared to the thresholds.
+#[derive(Clone)]
+pub struct Column {
+    pub bands: [f32; BANDS],
+    pub level: f32,
+}
+
+#[derive(Default)]
+pub struct History {
+    columns: VecDeque<Column>,
+}
+
+impl History {
+    pub fn push(&mut self, column: Column) {
+        if self.columns.len() == COLUMNS {
+            self.columns.pop_front();
+        }
+        self.columns.push_back(column);
+    }
+}
+
+// Folds each block into log-spaced bands, normalized to 0..1.
+pub struct BandAnalyzer {
+    fft: Arc<dyn Fft<f32>>,
+    buffer: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+    band_edges: Vec<usize>,
+}
+
+impl BandAnalyzer {
+    pub fn new(block_size: usize, sample_rate: f32) -> Self {
+        let fft = FftPlanner::new().plan_fft_forward(block_size);
+        let scratch = vec![Complex::new(0.0, 0.0); fft.get_inplace_scratch_len()];
+
+        let nyquist_bin = block_size / 2;
+        let bin_hz = sample_rate / block_size as f32;
+        let ratio = (sample_rate / 2.0 / MIN_FREQ).powf(1.0 / BANDS as f32);
+        let mut band_edges = Vec::with_capacity(BANDS + 1);
+        let mut previous = 0;
+        for band in 0..=BANDS {
+            let bin = ((MIN_FREQ * ratio.powi(band as i32)) / bin_hz) as usize;
+            // Au moins un bin par bande, meme en basse frequence
+            let bin = bin.max(previous + 1).min(nyquist_bin);
+            band_edges.push(bin);
+            previous = bin;
+        }
+
+        BandAnalyzer {
+            fft,
+            buffer: vec![Complex::new(0.0, 0.0); block_size],
+            scratch,
+            band_edges,
+        }
+    }
+
+    pub fn bands(&mut self, block: &[f32]) -> [f32; BANDS] {
+        for (slot, &x) in self.buffer.iter_mut().zip(block) {
+            *slot = Complex::new(x, 0.0);
+        }
+        self.fft
+            .process_with_scratch(&mut self.buffer, &mut self.scratch);
+
+        let scale = 2.0 / block.len() as f32;
+        let mut bands = [0.0; BANDS];
+        for (band, edges) in bands.iter_mut().zip(self.band_edges.windows(2)) {
+            let peak = self.buffer[edges[0]..edges[1].max(edges[0] + 1)]
+                .iter()
+                .map(|c| c.norm() * scale)
+                .fold(0.0, f32::max);
+            let db = 20.0 * peak.max(1e-9).log10();
+            *band = ((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0);
+        }
+        bands
+    }
+
+    // Magnitudes from DC to Nyquist of the block last passed to `bands`.
+    pub fn magnitudes(&self, out: &mut Vec<f32>) {
+        let scale = 2.0 / self.buffer.len() as f32;
+        out.clear();
+        out.extend(
+            self.buffer[..=self.buffer.len() / 2]
+                .iter()
+                .map(|c| c.norm() * scale),
+        );
+    }
+}
+
+// Top of the level scale: the highest level shown or 1.5 times the top threshold.
+fn max_level(history: &History, thresholds: &[f32; 3]) -> f32 {
+    history
+        .columns
+        .iter()
+        .map(|column| column.level)
+        .fold(thresholds[2] * 1.5, f32::max)
+        // Seuils a zero et silence : pas de division par zero
+        .max(f32::EPSILON)
+}
+
+fn heat(value: f32) -> [f32; 4] {
+    // Noir -> bleu -> jaune
+    [value.powi(2), value, (1.0 - value) * value * 4.0, 0.85]
+}
+
+// Draws the spectrogram along the bottom edge, the level trace and the thresholds.
+pub fn draw(
+    history: &History,
+    thresholds: &[f32; 3],
+    width: f64,
+    height: f64,
+    c: Context,
+    g: &mut G2d,
+) {
+    let top = height - HEIGHT;
+    let column_width = width / COLUMNS as f64;
+    let band_height = HEIGHT / BANDS as f64;
+
+    rectangle(
+        [0.0, 0.0, 0.0, 0.7],
+        [0.0, top, width, HEIGHT],
+        c.transform,
+        g,
+    );
+
+    // Les colonnes les plus recentes sont a droite
+    let offset = COLUMNS - history.columns.len();
+    for (i, column) in history.columns.iter().enumerate() {
+        let x = (offset + i) as f64 * column_width;
+        for (band, &value) in column.bands.iter().enumerate() {
+            if value <= 0.0 {
+                continue;
+            }
+            let y = height - (band + 1) as f64 * band_height;
+            rectangle(
+                heat(value),
+                [x, y, column_width, band_height],
+                c.transform,
+                g,
+            );
+        }
+    }
+
+    let max_level = max_level(history, thresholds);
+    let level_y = |level: f32| height - (level / max_level) as f64 * HEIGHT;
+
+    for (threshold, color) in thresholds.iter().zip(THRESHOLD_COLORS) {
+        let y = level_y(*threshold);
+        line(color, 1.0, [0.0, y, width, y], c.transform, g);
+    }
+
+    let pairs = history.columns.iter().zip(history.columns.iter().skip(1));
+    for (i, (from, to)) in pairs.enumerate() {
+        let x = (offset + i) as f64 * column_width;
+        line(
+            [1.0, 1.0, 1.0, 1.0],
+            0.8,
+            [x, level_y(from.level), x + column_width, level_y(to.level)],
+            c.transform,
+            g,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_sine_raises_only_its_band() {
+        let mut analyzer = BandAnalyzer::new(512, 48000.0);
+        // 20 periodes exactes dans le bloc : toute l'energie tombe dans le bin 20
+        let block: Vec<f32> = (0..512)
+            .map(|n| (2.0 * std::f32::consts::PI * 20.0 * n as f32 / 512.0).sin())
+            .collect();
+        let bands = analyzer.bands(&block);
+        let band = analyzer
+            .band_edges
+            .windows(2)
+            .position(|edges| (edges[0]..edges[1]).contains(&20))
+            .unwrap();
+        for (index, &value) in bands.iter().enumerate() {
+            if index == band {
+                assert!(value > 0.99, "{}", value);
+            } else {
+                assert!(value < 0.1, "band {}: {}", index, value);
+            }
+        }
+        assert!(analyzer
+            .band_edges
+            .windows(2)
+            .all(|edges| edges[0] < edges[1]));
+    }
+
+    #[test]
+    fn silence_with_zero_thresholds_has_a_finite_scale() {
+        let mut history = History::default();
+        assert_eq!(max_level(&history, &[0.0; 3]), f32::EPSILON);
+        history.push(Column {
+            bands: [0.0; BANDS],
+            level: 0.0,
+        });
+        let max = max_level(&history, &[0.0; 3]);
+        assert!(max > 0.0 && (0.0 / max).is_finite());
+
+        history.push(Column {
+            bands: [0.0; BANDS],
+            level: 4.0,
+        });
+        assert_eq!(max_level(&history, &[1.0, 2.0, 2.0]), 4.0);
+    }
+}
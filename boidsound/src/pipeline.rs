@@ -0,0 +1,194 @@
+use cpal::{InputCallbackInfo, StreamInstant};
+use rtrb::{Consumer, Producer, RingBuffer};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+// Un callback plus en retard que ca sur la duree du precedent compte comme un xrun
+const XRUN_TOLERANCE: f64 = 1.5;
+
+// Position de la cible partagee sans verrou : x et y en f32 dans un seul mot,
+// pour qu'une lecture ne melange jamais deux positions
+pub struct SharedTarget(AtomicU64);
+
+impl SharedTarget {
+    pub fn new(target: (f64, f64)) -> Self {
+        SharedTarget(AtomicU64::new(pack(target)))
+    }
+
+    pub fn load(&self) -> (f64, f64) {
+        let bits = self.0.load(Ordering::Relaxed);
+        (
+            f32::from_bits((bits >> 32) as u32) as f64,
+            f32::from_bits(bits as u32) as f64,
+        )
+    }
+
+    pub fn store(&self, target: (f64, f64)) {
+        self.0.store(pack(target), Ordering::Relaxed);
+    }
+}
+
+fn pack((x, y): (f64, f64)) -> u64 {
+    ((x as f32).to_bits() as u64) << 32 | (y as f32).to_bits() as u64
+}
+
+#[derive(Default)]
+pub struct PipelineStats {
+    pub dropped_frames: AtomicU64,
+    pub xruns: AtomicU64,
+}
+
+impl PipelineStats {
+    // Prints the counters when they changed since the last call.
+    pub fn report(&self, last: &mut (u64, u64)) {
+        let current = (
+            self.dropped_frames.load(Ordering::Relaxed),
+            self.xruns.load(Ordering::Relaxed),
+        );
+        if current != *last {
+            eprintln!(
+                "Audio pipeline: {} dropped frames, {} xruns",
+                current.0, current.1
+            );
+            *last = current;
+        }
+    }
+}
+
+// Audio thread side of the pipeline: never locks nor allocates.
+pub struct SampleSender {
+    producer: Producer<f32>,
+    stats: Arc<PipelineStats>,
+    channels: usize,
+    sample_rate: f64,
+    last_capture: Option<(StreamInstant, usize)>,
+}
+
+impl SampleSender {
+    // Without `info` (a source other than a sound card) xruns are not counted.
+    pub fn push(&mut self, data: &[f32], info: Option<&InputCallbackInfo>) {
+        if let Some(info) = info {
+            let capture = info.timestamp().capture;
+            if let Some((previous, previous_frames)) = self.last_capture {
+                if let Some(gap) = capture.duration_since(&previous) {
+                    let expected = previous_frames as f64 / self.sample_rate;
+                    if gap.as_secs_f64() > expected * XRUN_TOLERANCE {
+                        self.stats.xruns.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }
+            // Un callback decoupe en blocs par device.rs garde le meme horodatage
+            let frames = data.len() / self.channels;
+            self.last_capture = match self.last_capture {
+                Some((previous, previous_frames)) if previous == capture => {
+                    Some((capture, previous_frames + frames))
+                }
+                _ => Some((capture, frames)),
+            };
+        }
+
+        // Trames entieres seulement, sinon les canaux seraient decales pour la suite
+        let written = (data.len().min(self.producer.slots()) / self.channels) * self.channels;
+        if let Ok(chunk) = self.producer.write_chunk_uninit(written) {
+            chunk.fill_from_iter(data.iter().copied());
+        }
+        let dropped = (data.len() - written) / self.channels;
+        if dropped > 0 {
+            self.stats
+                .dropped_frames
+                .fetch_add(dropped as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+// Analysis thread side: hands out fixed-size frames from the ring buffer.
+pub struct FrameReader {
+    consumer: Consumer<f32>,
+    frame: Vec<f32>,
+    filled: usize,
+}
+
+impl FrameReader {
+    pub fn next_frame(&mut self) -> Option<&[f32]> {
+        let wanted = (self.frame.len() - self.filled).min(self.consumer.slots());
+        if let Ok(chunk) = self.consumer.read_chunk(wanted) {
+            let (first, second) = chunk.as_slices();
+            self.frame[self.filled..self.filled + first.len()].copy_from_slice(first);
+            self.filled += first.len();
+            self.frame[self.filled..self.filled + second.len()].copy_from_slice(second);
+            self.filled += second.len();
+            chunk.commit_all();
+        }
+
+        if self.filled == self.frame.len() {
+            self.filled = 0;
+            Some(&self.frame)
+        } else {
+            None
+        }
+    }
+}
+
+// The ring buffer holds `capacity` samples, the reader hands out `frame_size` at a time.
+pub fn sample_channel(
+    capacity: usize,
+    frame_size: usize,
+    channels: usize,
+    sample_rate: u32,
+) -> (SampleSender, FrameReader, Arc<PipelineStats>) {
+    let (producer, consumer) = RingBuffer::new(capacity);
+    let stats = Arc::new(PipelineStats::default());
+    let sender = SampleSender {
+        producer,
+        stats: Arc::clone(&stats),
+        channels: channels.max(1),
+        sample_rate: sample_rate as f64,
+        last_capture: None,
+    };
+    let reader = FrameReader {
+        consumer,
+        frame: vec![0.0; frame_size],
+        filled: 0,
+    };
+    (sender, reader, stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn target_keeps_both_coordinates() {
+        let target = SharedTarget::new((400.0, 300.0));
+        assert_eq!(target.load(), (400.0, 300.0));
+        target.store((-12.5, 1e6));
+        assert_eq!(target.load(), (-12.5, 1e6));
+        // Precision d'un f32, largement suffisante pour des pixels
+        target.store((123.456789, 0.1));
+        let (x, y) = target.load();
+        assert!((x - 123.456789).abs() < 1e-4 && (y - 0.1).abs() < 1e-7);
+    }
+
+    #[test]
+    fn overflow_drops_whole_frames() {
+        // 9 places : apres 3 trames stereo, il ne reste de la place que pour une trame et demie
+        let (mut sender, mut reader, stats) = sample_channel(9, 4, 2, 48000);
+        let frames = |range: std::ops::Range<i32>| {
+            range
+                .flat_map(|frame| [frame as f32, -(frame as f32)])
+                .collect::<Vec<_>>()
+        };
+        sender.push(&frames(1..4), None);
+        sender.push(&frames(4..7), None);
+        assert_eq!(stats.dropped_frames.load(Ordering::Relaxed), 2);
+
+        // Gauche positif, droite negatif, dans l'ordre
+        assert_eq!(reader.next_frame(), Some(&[1.0, -1.0, 2.0, -2.0][..]));
+        assert_eq!(reader.next_frame(), Some(&[3.0, -3.0, 4.0, -4.0][..]));
+        sender.push(&frames(7..8), None);
+        assert_eq!(reader.next_frame(), None);
+        sender.push(&frames(8..9), None);
+        assert_eq!(reader.next_frame(), Some(&[7.0, -7.0, 8.0, -8.0][..]));
+        assert_eq!(stats.dropped_frames.load(Ordering::Relaxed), 2);
+    }
+}
@@ -0,0 +1,329 @@
+use crate::{device, source, Boid, Obstacle, HEIGHT, WIDTH};
+use cpal::traits::StreamTrait;
+use cpal::Stream;
+use hound::WavReader;
+use piston_window::*;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+// Un enregistrement produit <nom>.wav pour l'audio et <nom>.log pour l'etat de la simulation.
+//
+// Format du log, une ligne par enregistrement :
+//   obstacle <x> <y> <w> <h>
+//   frame <secondes> <cible x> <cible y> <r> <g> <b> puis <x> <y> <vx> <vy> pour chaque boid
+pub fn wav_path(name: &str) -> String {
+    format!("{}.wav", name)
+}
+
+pub fn log_path(name: &str) -> String {
+    format!("{}.log", name)
+}
+
+pub struct Frame {
+    pub time: f64,
+    pub target: (f64, f64),
+    pub color: [f32; 4],
+    pub boids: Vec<Boid>,
+}
+
+pub struct Recorder {
+    out: BufWriter<File>,
+}
+
+impl Recorder {
+    pub fn create(name: &str, obstacles: &[Obstacle]) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(log_path(name))?);
+        for obs in obstacles {
+            writeln!(out, "obstacle {} {} {} {}", obs.x, obs.y, obs.w, obs.h)?;
+        }
+        Ok(Recorder { out })
+    }
+
+    pub fn write_frame(
+        &mut self,
+        time: f64,
+        target: (f64, f64),
+        color: [f32; 4],
+        boids: &[Boid],
+    ) -> io::Result<()> {
+        write!(
+            self.out,
+            "frame {} {} {} {} {} {}",
+            time, target.0, target.1, color[0], color[1], color[2]
+        )?;
+        for boid in boids {
+            write!(self.out, " {} {} {} {}", boid.x, boid.y, boid.vx, boid.vy)?;
+        }
+        writeln!(self.out)
+    }
+}
+
+pub struct Session {
+    pub obstacles: Vec<Obstacle>,
+    pub frames: Vec<Frame>,
+}
+
+impl Session {
+    pub fn load(name: &str) -> Result<Self, String> {
+        let path = log_path(name);
+        let content =
+            fs::read_to_string(&path).map_err(|err| format!("Failed to read {}: {}", path, err))?;
+
+        let mut session = Session {
+            obstacles: Vec::new(),
+            frames: Vec::new(),
+        };
+        for (number, line) in content.lines().enumerate() {
+            let mut words = line.split_whitespace();
+            let kind = words.next();
+            let values: Vec<f64> = words
+                .map(|word| word.parse())
+                .collect::<Result<_, _>>()
+                .map_err(|_| format!("{}:{}: invalid number", path, number + 1))?;
+            match (kind, values.as_slice()) {
+                (Some("obstacle"), &[x, y, w, h]) => {
+                    session.obstacles.push(Obstacle { x, y, w, h })
+                }
+                (Some("frame"), [time, target_x, target_y, r, g, b, rest @ ..])
+                    if rest.len() % 4 == 0 =>
+                {
+                    session.frames.push(Frame {
+                        time: *time,
+                        target: (*target_x, *target_y),
+                        color: [*r as f32, *g as f32, *b as f32, 1.0],
+                        boids: rest
+                            .chunks(4)
+                            .map(|b| Boid {
+                                x: b[0],
+                                y: b[1],
+                                vx: b[2],
+                                vy: b[3],
+                            })
+                            .collect(),
+                    })
+                }
+                (None, _) => {}
+                _ => return Err(format!("{}:{}: unexpected line", path, number + 1)),
+            }
+        }
+        Ok(session)
+    }
+
+    // Last frame recorded at or before `time`.
+    pub fn frame_at(&self, time: f64) -> Option<&Frame> {
+        let index = self.frames.partition_point(|frame| frame.time <= time);
+        self.frames.get(index.saturating_sub(1))
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.frames.last().map_or(0.0, |frame| frame.time)
+    }
+}
+
+// Recorded audio, interleaved.
+pub struct Recording {
+    pub samples: Vec<f32>,
+    pub channels: usize,
+    pub sample_rate: u32,
+}
+
+impl Recording {
+    // None when the session has no wav (recorded with midi=).
+    pub fn load(name: &str) -> Result<Option<Self>, String> {
+        let path = wav_path(name);
+        if !Path::new(&path).exists() {
+            return Ok(None);
+        }
+        let reader =
+            WavReader::open(&path).map_err(|err| format!("Failed to open {}: {}", path, err))?;
+        let spec = reader.spec();
+        let samples = source::read_samples(reader).map_err(|err| format!("{}: {}", path, err))?;
+        Ok(Some(Recording {
+            samples,
+            channels: spec.channels.max(1) as usize,
+            sample_rate: spec.sample_rate,
+        }))
+    }
+
+    // Plays the audio on `output` (or the default output) from frame `position`,
+    // which the callback advances: the replay uses it as its clock.
+    fn play(self, output: Option<&str>, position: Arc<AtomicUsize>) -> Result<Stream, String> {
+        let (device, config) = device::open_output(output, self.sample_rate)?;
+        let output_channels = config.channels() as usize;
+        let stream = device::build_output_stream(
+            &device,
+            &config,
+            move |data| {
+                let mut frame = position.load(Ordering::Relaxed);
+                for out in data.chunks_mut(output_channels) {
+                    let start = frame * self.channels;
+                    for (channel, sample) in out.iter_mut().enumerate() {
+                        // Un wav mono sort sur tous les canaux
+                        *sample = self
+                            .samples
+                            .get(start + channel % self.channels)
+                            .copied()
+                            .unwrap_or(0.0);
+                    }
+                    frame += 1;
+                }
+                position.store(frame, Ordering::Relaxed);
+            },
+            |err| eprintln!("Error: {:?}", err),
+        )?;
+        stream
+            .play()
+            .map_err(|err| format!("Failed to start output stream: {}", err))?;
+        Ok(stream)
+    }
+}
+
+// Plays back a recorded session at its original pace, with its audio on
+// the default output when there is one. Space restarts it, T shows the
+// recorded target.
+pub fn replay(name: &str, output: Option<&str>) -> Result<(), String> {
+    let session = Session::load(name)?;
+    println!(
+        "Replaying {} frames ({:.1} s) from {}",
+        session.frames.len(),
+        session.duration(),
+        log_path(name)
+    );
+
+    // L'horloge est la position de lecture du wav, sinon le temps ecoule
+    let position = Arc::new(AtomicUsize::new(0));
+    let audio = match Recording::load(name)? {
+        Some(recording) => {
+            let sample_rate = recording.sample_rate as f64;
+            match recording.play(output, Arc::clone(&position)) {
+                Ok(stream) => {
+                    println!("Playing {}", wav_path(name));
+                    Some((stream, sample_rate))
+                }
+                Err(err) => {
+                    eprintln!("{}: replaying without sound", err);
+                    None
+                }
+            }
+        }
+        None => {
+            println!("No {}, replaying without sound", wav_path(name));
+            None
+        }
+    };
+
+    let mut window: PistonWindow = WindowSettings::new(
+        "Boids under sound influence (replay)",
+        [WIDTH as u32, HEIGHT as u32],
+    )
+    .exit_on_esc(true)
+    .build()
+    .unwrap();
+
+    let mut start = Instant::now();
+    let mut show_target = false;
+    while let Some(event) = window.next() {
+        match event.press_args() {
+            Some(Button::Keyboard(Key::Space)) => {
+                start = Instant::now();
+                position.store(0, Ordering::Relaxed);
+            }
+            Some(Button::Keyboard(Key::T)) => show_target = !show_target,
+            _ => {}
+        }
+
+        let time = match &audio {
+            Some((_, sample_rate)) => position.load(Ordering::Relaxed) as f64 / sample_rate,
+            None => start.elapsed().as_secs_f64(),
+        };
+        let frame = session.frame_at(time);
+        window.draw_2d(&event, |c, g, _| {
+            clear([0.0; 4], g);
+            for obs in &session.obstacles {
+                obs.draw(c, g);
+            }
+            if let Some(frame) = frame {
+                for boid in &frame.boids {
+                    boid.draw(frame.color, c, g);
+                }
+                if show_target {
+                    let (x, y) = frame.target;
+                    let color = [1.0, 1.0, 1.0, 0.8];
+                    line(color, 1.0, [x - 8.0, y, x + 8.0, y], c.transform, g);
+                    line(color, 1.0, [x, y - 8.0, x, y + 8.0], c.transform, g);
+                }
+            }
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_round_trip() {
+        let name = std::env::temp_dir().join("boidsound_session_test");
+        let name = name.to_str().unwrap();
+        let obstacles = [Obstacle {
+            x: 10.0,
+            y: 20.0,
+            w: 30.5,
+            h: 40.0,
+        }];
+        let boids = [
+            Boid {
+                x: 1.5,
+                y: 2.0,
+                vx: -0.25,
+                vy: 3.0,
+            },
+            Boid {
+                x: 100.0,
+                y: 200.0,
+                vx: 0.1,
+                vy: -0.2,
+            },
+        ];
+        {
+            let mut recorder = Recorder::create(name, &obstacles).unwrap();
+            recorder
+                .write_frame(0.0, (5.0, 6.0), [1.0, 0.5, 0.0, 1.0], &boids[..1])
+                .unwrap();
+            recorder
+                .write_frame(0.04, (7.5, 8.0), [0.0, 0.25, 1.0, 1.0], &boids)
+                .unwrap();
+        }
+
+        let session = Session::load(name).unwrap();
+        fs::remove_file(log_path(name)).ok();
+        assert_eq!(session.obstacles.len(), 1);
+        let obstacle = &session.obstacles[0];
+        assert_eq!(
+            (obstacle.x, obstacle.y, obstacle.w, obstacle.h),
+            (10.0, 20.0, 30.5, 40.0)
+        );
+
+        assert_eq!(session.frames.len(), 2);
+        let frame = &session.frames[1];
+        assert_eq!(frame.time, 0.04);
+        assert_eq!(frame.target, (7.5, 8.0));
+        assert_eq!(frame.color, [0.0, 0.25, 1.0, 1.0]);
+        let boid = &frame.boids[1];
+        assert_eq!(
+            (boid.x, boid.y, boid.vx, boid.vy),
+            (100.0, 200.0, 0.1, -0.2)
+        );
+        assert_eq!(session.frames[0].boids.len(), 1);
+
+        // La derniere image anterieure, meme entre deux images
+        assert_eq!(session.frame_at(0.02).unwrap().time, 0.0);
+        assert_eq!(session.frame_at(1.0).unwrap().time, 0.04);
+        assert_eq!(session.duration(), 0.04);
+    }
+}
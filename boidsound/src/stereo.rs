@@ -0,0 +1,170 @@
+// Ecart de niveau (dB) qui place la cible tout a gauche ou tout a droite
+const LEVEL_RANGE_DB: f32 = 12.0;
+// Decalage maximal cherche entre les canaux, en secondes
+const MAX_DELAY: f32 = 0.001;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum StereoMode {
+    Off,
+    Level,
+    Time,
+}
+
+impl StereoMode {
+    pub fn from_arg(value: Option<&str>) -> Self {
+        match value {
+            Some("level") => StereoMode::Level,
+            Some("time") => StereoMode::Time,
+            Some("off") | None => StereoMode::Off,
+            Some(other) => {
+                eprintln!(
+                    "Unknown stereo mode '{}', expected level, time or off",
+                    other
+                );
+                StereoMode::Off
+            }
+        }
+    }
+}
+
+// Splits interleaved blocks into left, right and mono, reusing its buffers.
+pub struct StereoSplitter {
+    channels: usize,
+    pub left: Vec<f32>,
+    pub right: Vec<f32>,
+    pub mono: Vec<f32>,
+}
+
+impl StereoSplitter {
+    pub fn new(channels: usize, frames: usize) -> Self {
+        StereoSplitter {
+            channels: channels.max(1),
+            left: Vec::with_capacity(frames),
+            right: Vec::with_capacity(frames),
+            mono: Vec::with_capacity(frames),
+        }
+    }
+
+    pub fn split(&mut self, interleaved: &[f32]) {
+        self.left.clear();
+        self.right.clear();
+        self.mono.clear();
+        for frame in interleaved.chunks(self.channels) {
+            self.left.push(frame[0]);
+            // Une entree mono sert aux deux cotes
+            self.right.push(frame[1.min(frame.len() - 1)]);
+            self.mono
+                .push(frame.iter().sum::<f32>() / frame.len() as f32);
+        }
+    }
+
+    // Horizontal position of the sound from -1 (left) to 1 (right).
+    pub fn balance(&self, mode: StereoMode, sample_rate: u32) -> f32 {
+        match mode {
+            StereoMode::Off => 0.0,
+            StereoMode::Level => level_balance(&self.left, &self.right),
+            StereoMode::Time => {
+                let max_lag = (MAX_DELAY * sample_rate as f32) as usize;
+                time_balance(&self.left, &self.right, max_lag)
+            }
+        }
+    }
+}
+
+// Inter-channel level difference, scaled so LEVEL_RANGE_DB reaches the edges.
+pub fn level_balance(left: &[f32], right: &[f32]) -> f32 {
+    let energy = |samples: &[f32]| samples.iter().map(|&x| x * x).sum::<f32>();
+    let (left, right) = (energy(left), energy(right));
+    if left + right <= 0.0 {
+        return 0.0;
+    }
+    // 10 * log10 sur l'energie donne les dB d'amplitude
+    let difference_db = 10.0 * ((right + 1e-12) / (left + 1e-12)).log10();
+    (difference_db / LEVEL_RANGE_DB).clamp(-1.0, 1.0)
+}
+
+// Inter-channel time difference from the peak of the cross-correlation:
+// when the right channel lags behind, the sound reached the left side first.
+pub fn time_balance(left: &[f32], right: &[f32], max_lag: usize) -> f32 {
+    let max_lag = max_lag.min(left.len().saturating_sub(1)) as isize;
+    if max_lag == 0 {
+        return 0.0;
+    }
+    let len = left.len().min(right.len()) as isize;
+    let correlation = |lag: isize| {
+        (0.max(-lag)..len.min(len - lag))
+            .map(|i| left[i as usize] * right[(i + lag) as usize])
+            .sum::<f32>()
+    };
+    let (best_lag, best) = (-max_lag..=max_lag)
+        .map(|lag| (lag, correlation(lag)))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap_or((0, 0.0));
+    if best <= 0.0 {
+        return 0.0;
+    }
+    -(best_lag as f32 / max_lag as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    fn noise(len: usize) -> Vec<f32> {
+        let mut rng = StdRng::seed_from_u64(7);
+        (0..len).map(|_| rng.gen_range(-1.0..=1.0)).collect()
+    }
+
+    // Le canal droit recoit le bruit `delay` echantillons apres le gauche
+    // (un delai negatif retarde le gauche)
+    fn delayed(delay: isize) -> StereoSplitter {
+        let len = 2048;
+        let signal = noise(len + delay.unsigned_abs());
+        let early = &signal[delay.unsigned_abs()..];
+        let late = &signal[..len];
+        let (left, right) = if delay >= 0 {
+            (early, late)
+        } else {
+            (late, early)
+        };
+        let interleaved: Vec<f32> = left.iter().zip(right).flat_map(|(&l, &r)| [l, r]).collect();
+        let mut splitter = StereoSplitter::new(2, len);
+        splitter.split(&interleaved);
+        splitter
+    }
+
+    #[test]
+    fn level_balance_follows_the_louder_side() {
+        let signal = noise(1024);
+        let silence = vec![0.0; 1024];
+        assert_eq!(level_balance(&signal, &silence), -1.0);
+        assert_eq!(level_balance(&silence, &signal), 1.0);
+        assert_eq!(level_balance(&signal, &signal), 0.0);
+        // 6 dB plus fort a droite : la moitie de la plage de 12 dB
+        let louder: Vec<f32> = signal.iter().map(|x| x * 2.0).collect();
+        assert!((level_balance(&signal, &louder) - 0.5).abs() < 0.01);
+        assert_eq!(level_balance(&silence, &silence), 0.0);
+    }
+
+    #[test]
+    fn time_balance_points_to_the_earlier_side() {
+        // 0.5 ms = 24 echantillons, la moitie de la plage de 1 ms
+        let right_late = delayed(24);
+        assert_eq!(right_late.balance(StereoMode::Time, SAMPLE_RATE), -0.5);
+        let left_late = delayed(-24);
+        assert_eq!(left_late.balance(StereoMode::Time, SAMPLE_RATE), 0.5);
+        assert_eq!(delayed(0).balance(StereoMode::Time, SAMPLE_RATE), 0.0);
+
+        // 1 ms atteint le bord, au-dela le decalage n'est plus cherche
+        assert_eq!(delayed(48).balance(StereoMode::Time, SAMPLE_RATE), -1.0);
+        assert_eq!(delayed(-48).balance(StereoMode::Time, SAMPLE_RATE), 1.0);
+        for delay in [96, -96] {
+            let balance = delayed(delay).balance(StereoMode::Time, SAMPLE_RATE);
+            assert!((-1.0..=1.0).contains(&balance), "{}", balance);
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+pub const FRAME_SIZE: usize = 2048;
+pub const HOP_SIZE: usize = 512;
+// Plancher des niveaux, pour ne pas afficher -inf sur du silence
+pub const MIN_DECIBELS: f32 = -120.0;
+
+// Nombre de bins de part et d'autre du pic comptes dans le lobe principal de la fenetre de Hann
+const MAIN_LOBE: usize = 2;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Analysis {
+    pub decibels: f32,
+    pub peak_decibels: f32,
+    pub pitch: f32,
+    // Share of the spectral energy in the peak's main lobe: 1 for a pure tone, near 0 for noise.
+    pub confidence: f32,
+}
+
+// Fixed-size frames with overlap: each call to `push` adds one hop of samples
+// and analyzes the last FRAME_SIZE of them. The FFT is planned once and all
+// buffers are reused.
+pub struct Analyzer {
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    frame: Vec<f32>,
+    buffer: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+    magnitudes: Vec<f32>,
+    sample_rate: f32,
+}
+
+impl Analyzer {
+    pub fn new(frame_size: usize, sample_rate: f32) -> Self {
+        let fft = FftPlanner::new().plan_fft_forward(frame_size);
+        let scratch = vec![Complex::new(0.0, 0.0); fft.get_inplace_scratch_len()];
+        let window = (0..frame_size)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / frame_size as f32).cos())
+            .collect();
+        Analyzer {
+            fft,
+            window,
+            frame: vec![0.0; frame_size],
+            buffer: vec![Complex::new(0.0, 0.0); frame_size],
+            scratch,
+            magnitudes: vec![0.0; frame_size / 2 + 1],
+            sample_rate,
+        }
+    }
+
+    // Magnitude spectrum of the last analyzed frame, from DC to Nyquist.
+    pub fn magnitudes(&self) -> &[f32] {
+        &self.magnitudes
+    }
+
+    fn bin_frequency(&self, bin: f32) -> f32 {
+        bin * self.sample_rate / self.frame.len() as f32
+    }
+
+    pub fn push(&mut self, hop: &[f32]) -> Analysis {
+        let hop = &hop[hop.len().saturating_sub(self.frame.len())..];
+        self.frame.rotate_left(hop.len());
+        let start = self.frame.len() - hop.len();
+        self.frame[start..].copy_from_slice(hop);
+
+        let rms = (self.frame.iter().map(|&x| x * x).sum::<f32>() / self.frame.len() as f32).sqrt();
+        let decibels = (20.0 * rms.log10()).max(MIN_DECIBELS);
+        let peak = self.frame.iter().fold(0.0f32, |peak, &x| peak.max(x.abs()));
+        let peak_decibels = (20.0 * peak.log10()).max(MIN_DECIBELS);
+
+        // Analyse du pitch, sans la composante continue qui deborde sur les premiers bins
+        let mean = self.frame.iter().sum::<f32>() / self.frame.len() as f32;
+        for ((slot, &x), &w) in self.buffer.iter_mut().zip(&self.frame).zip(&self.window) {
+            *slot = Complex::new((x - mean) * w, 0.0);
+        }
+        self.fft
+            .process_with_scratch(&mut self.buffer, &mut self.scratch);
+        for (magnitude, complex) in self.magnitudes.iter_mut().zip(&self.buffer) {
+            *magnitude = complex.norm();
+        }
+
+        let (pitch, confidence) = self.find_peak();
+        Analysis {
+            decibels,
+            peak_decibels,
+            pitch,
+            confidence,
+        }
+    }
+
+    // Largest bin between DC and Nyquist (both excluded), refined by fitting a
+    // parabola through the log magnitudes of the peak and its neighbours.
+    fn find_peak(&self) -> (f32, f32) {
+        let nyquist = self.magnitudes.len() - 1;
+        let Some(peak) =
+            (1..nyquist).max_by(|&a, &b| self.magnitudes[a].total_cmp(&self.magnitudes[b]))
+        else {
+            return (0.0, 0.0);
+        };
+
+        let energy = |range: std::ops::Range<usize>| {
+            self.magnitudes[range].iter().map(|&m| m * m).sum::<f32>()
+        };
+        let total = energy(1..nyquist);
+        if total <= 0.0 {
+            return (0.0, 0.0);
+        }
+        let lobe =
+            energy(peak.saturating_sub(MAIN_LOBE).max(1)..(peak + MAIN_LOBE + 1).min(nyquist));
+        let confidence = lobe / total;
+
+        let (a, b, c) = (
+            (self.magnitudes[peak - 1] + 1e-12).ln(),
+            (self.magnitudes[peak] + 1e-12).ln(),
+            (self.magnitudes[peak + 1] + 1e-12).ln(),
+        );
+        let denominator = a - 2.0 * b + c;
+        let offset = if denominator.abs() > f32::EPSILON {
+            (0.5 * (a - c) / denominator).clamp(-0.5, 0.5)
+        } else {
+            0.0
+        };
+        (self.bin_frequency(peak as f32 + offset), confidence)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    fn sine(frequency: f32, amplitude: f32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| amplitude * (2.0 * PI * frequency * i as f32 / SAMPLE_RATE).sin())
+            .collect()
+    }
+
+    fn analyze(signal: &[f32]) -> Analysis {
+        let mut analyzer = Analyzer::new(FRAME_SIZE, SAMPLE_RATE);
+        let mut result = Analysis::default();
+        for hop in signal.chunks(HOP_SIZE) {
+            result = analyzer.push(hop);
+        }
+        result
+    }
+
+    #[test]
+    fn sine_pitch_is_accurate_between_bins() {
+        // La resolution d'un bin est d'environ 23 Hz, on attend bien mieux
+        for frequency in [82.4, 110.0, 440.0, 1000.0, 3520.0, 12345.0] {
+            let result = analyze(&sine(frequency, 0.5, FRAME_SIZE * 2));
+            assert!(
+                (result.pitch - frequency).abs() < frequency * 0.002 + 0.5,
+                "expected {} Hz, got {} Hz",
+                frequency,
+                result.pitch
+            );
+            assert!(result.confidence > 0.9, "confidence {}", result.confidence);
+        }
+    }
+
+    #[test]
+    fn dc_offset_is_ignored() {
+        let signal: Vec<f32> = sine(440.0, 0.2, FRAME_SIZE * 2)
+            .iter()
+            .map(|x| x + 0.5)
+            .collect();
+        let result = analyze(&signal);
+        assert!(
+            (result.pitch - 440.0).abs() < 1.5,
+            "got {} Hz",
+            result.pitch
+        );
+    }
+
+    #[test]
+    fn level_follows_amplitude() {
+        // Un sinus d'amplitude 1 a un RMS de 1/sqrt(2), soit -3 dB
+        let result = analyze(&sine(1000.0, 1.0, FRAME_SIZE * 2));
+        assert!(
+            (result.decibels + 3.01).abs() < 0.05,
+            "got {} dB",
+            result.decibels
+        );
+        let quieter = analyze(&sine(1000.0, 0.1, FRAME_SIZE * 2));
+        assert!((result.decibels - quieter.decibels - 20.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn noise_has_low_confidence() {
+        let mut state = 12345u32;
+        let noise: Vec<f32> = (0..FRAME_SIZE * 2)
+            .map(|_| {
+                state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+                (state >> 8) as f32 / (1u32 << 24) as f32 - 0.5
+            })
+            .collect();
+        let result = analyze(&noise);
+        assert!(result.confidence < 0.1, "confidence {}", result.confidence);
+    }
+
+    #[test]
+    fn overlapping_hops_track_a_pitch_change() {
+        let mut analyzer = Analyzer::new(FRAME_SIZE, SAMPLE_RATE);
+        for hop in sine(220.0, 0.5, FRAME_SIZE).chunks(HOP_SIZE) {
+            analyzer.push(hop);
+        }
+        let mut result = Analysis::default();
+        for hop in sine(660.0, 0.5, FRAME_SIZE).chunks(HOP_SIZE) {
+            result = analyzer.push(hop);
+        }
+        assert!(
+            (result.pitch - 660.0).abs() < 2.0,
+            "got {} Hz",
+            result.pitch
+        );
+    }
+}
@@ -0,0 +1,244 @@
+use crate::analysis::Analysis;
+use crate::loudness::{Loudness, TruePeak};
+use crate::tuner::Tuner;
+use crate::MIN_CONFIDENCE;
+use hound::{SampleFormat, WavSpec, WavWriter};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufWriter;
+
+// Largeur maximale des barres de l'histogramme, en caracteres
+const HISTOGRAM_WIDTH: usize = 40;
+
+// Levels of the whole capture, in dBFS, dBTP, LUFS and LU; None when silent.
+struct Levels {
+    peak: Option<f64>,
+    true_peak: Option<f64>,
+    integrated: Option<f64>,
+    range: Option<f64>,
+}
+
+// Records the input to a WAV file, optionally for a fixed duration, and
+// gathers what the report printed at the end needs.
+pub struct CaptureSession {
+    path: String,
+    writer: WavWriter<BufWriter<File>>,
+    channels: usize,
+    sample_rate: u32,
+    limit: Option<u64>,
+    frames: u64,
+    peak: f32,
+    loudness: Loudness,
+    true_peak: TruePeak,
+    tuner: Tuner,
+    // Nombre de hops par note, classees par (octave, classe de hauteur)
+    notes: BTreeMap<(i32, usize), (&'static str, u32)>,
+    pitched_hops: u32,
+    hops: u32,
+}
+
+impl CaptureSession {
+    pub fn create(
+        path: &str,
+        channels: usize,
+        sample_rate: u32,
+        seconds: Option<f64>,
+        tuner: Tuner,
+    ) -> Result<Self, String> {
+        let spec = WavSpec {
+            channels: channels as u16,
+            sample_rate,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        };
+        let writer = WavWriter::create(path, spec)
+            .map_err(|err| format!("Failed to create {}: {}", path, err))?;
+        Ok(CaptureSession {
+            path: path.to_string(),
+            writer,
+            channels,
+            sample_rate,
+            limit: seconds.map(|seconds| (seconds * sample_rate as f64).round() as u64),
+            frames: 0,
+            peak: 0.0,
+            loudness: Loudness::new(channels, sample_rate),
+            true_peak: TruePeak::new(channels),
+            tuner,
+            notes: BTreeMap::new(),
+            pitched_hops: 0,
+            hops: 0,
+        })
+    }
+
+    // Writes one hop of interleaved samples. Returns true once the requested
+    // duration is reached; the samples beyond it are not kept.
+    pub fn push(&mut self, hop: &[f32], analysis: &Analysis) -> Result<bool, String> {
+        let frames = (hop.len() / self.channels) as u64;
+        let kept = match self.limit {
+            Some(limit) => frames.min(limit - self.frames),
+            None => frames,
+        };
+        let hop = &hop[..kept as usize * self.channels];
+
+        for &sample in hop {
+            self.writer
+                .write_sample(sample)
+                .map_err(|err| format!("Failed to write {}: {}", self.path, err))?;
+            self.peak = self.peak.max(sample.abs());
+        }
+        self.frames += kept;
+        self.loudness.push(hop);
+        self.true_peak.push(hop);
+
+        self.hops += 1;
+        let note = if analysis.confidence >= MIN_CONFIDENCE {
+            self.tuner.nearest(analysis.pitch)
+        } else {
+            None
+        };
+        if let Some(note) = note {
+            let entry = self
+                .notes
+                .entry((note.octave, note.pitch_class))
+                .or_insert((note.name, 0));
+            entry.1 += 1;
+            self.pitched_hops += 1;
+        }
+
+        Ok(self.limit == Some(self.frames))
+    }
+
+    fn levels(&self) -> Levels {
+        Levels {
+            peak: (self.peak > 0.0).then(|| 20.0 * (self.peak as f64).log10()),
+            true_peak: self.true_peak.dbtp(),
+            integrated: self.loudness.integrated(),
+            range: self.loudness.range().map(|(low, high)| high - low),
+        }
+    }
+
+    // Finalizes the WAV file and prints the report.
+    pub fn finish(self) -> Result<(), String> {
+        let levels = self.levels();
+        let CaptureSession {
+            path,
+            writer,
+            channels,
+            sample_rate,
+            frames,
+            notes,
+            pitched_hops,
+            hops,
+            ..
+        } = self;
+        writer
+            .finalize()
+            .map_err(|err| format!("Failed to write {}: {}", path, err))?;
+
+        let level = |value: Option<f64>, unit: &str| {
+            value.map_or(String::from("-inf"), |v| format!("{:.1} {}", v, unit))
+        };
+        println!(
+            "Capture: {} ({:.1} s, {} channels, {} Hz)",
+            path,
+            frames as f64 / sample_rate as f64,
+            channels,
+            sample_rate
+        );
+        println!(
+            "Peak: {} sample, {} true peak",
+            level(levels.peak, "dBFS"),
+            level(levels.true_peak, "dBTP")
+        );
+        println!(
+            "Loudness: {} integrated, LRA {}",
+            level(levels.integrated, "LUFS"),
+            level(levels.range, "LU")
+        );
+
+        if pitched_hops == 0 {
+            println!("Pitch: no stable pitch detected");
+            return Ok(());
+        }
+        println!(
+            "Pitch histogram ({} of {} hops with a stable pitch):",
+            pitched_hops, hops
+        );
+        let most = notes.values().map(|(_, count)| *count).max().unwrap_or(1);
+        for ((octave, _), (name, count)) in &notes {
+            println!(
+                "  {:>3}{:<2} {:<width$} {:>5.1} %",
+                name,
+                octave,
+                "#".repeat((*count as usize * HISTOGRAM_WIDTH).div_ceil(most as usize)),
+                100.0 * *count as f64 / pitched_hops as f64,
+                width = HISTOGRAM_WIDTH
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(name: &str, seconds: Option<f64>) -> (CaptureSession, String) {
+        let path = std::env::temp_dir().join(name);
+        let path = path.to_str().unwrap().to_string();
+        let session =
+            CaptureSession::create(&path, 1, 48000, seconds, Tuner::from_args(&[]).unwrap())
+                .unwrap();
+        (session, path)
+    }
+
+    fn silence() -> Analysis {
+        Analysis {
+            decibels: -100.0,
+            peak_decibels: -100.0,
+            pitch: 0.0,
+            confidence: 0.0,
+        }
+    }
+
+    #[test]
+    fn stops_after_the_requested_duration() {
+        let (mut session, path) = session("sound01_capture_limit_test.wav", Some(0.1));
+        // 4800 trames attendues : 4 hops de 1024 puis 704 trames du cinquieme
+        let hop = vec![0.1; 1024];
+        for _ in 0..4 {
+            assert!(!session.push(&hop, &silence()).unwrap());
+        }
+        assert!(session.push(&hop, &silence()).unwrap());
+        session.finish().unwrap();
+
+        let reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.duration(), 4800);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn reports_the_levels_of_a_sine() {
+        let (mut session, path) = session("sound01_capture_levels_test.wav", None);
+        // Sinus a 1 kHz et -20 dBFS pendant 5 s : -23 LUFS (BS.1770)
+        let sine: Vec<f32> = (0..48000 * 5)
+            .map(|n| 0.1 * (2.0 * std::f32::consts::PI * 1000.0 * n as f32 / 48000.0).sin())
+            .collect();
+        for hop in sine.chunks(1024) {
+            session.push(hop, &silence()).unwrap();
+        }
+        let levels = session.levels();
+        assert!(
+            (levels.peak.unwrap() + 20.0).abs() < 0.01,
+            "{:?}",
+            levels.peak
+        );
+        let true_peak = levels.true_peak.unwrap();
+        assert!((true_peak + 20.0).abs() < 0.1, "{}", true_peak);
+        let integrated = levels.integrated.unwrap();
+        assert!((integrated + 23.0).abs() < 0.1, "{}", integrated);
+        assert!(levels.range.unwrap() < 0.1);
+        session.finish().unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
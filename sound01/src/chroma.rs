@@ -0,0 +1,264 @@
+use crate::tuner::NAMES;
+use piston_window::*;
+
+// Plage de frequences repliee sur les 12 classes de hauteur. Le plancher monte
+// jusqu'ou un bin de la FFT devient plus etroit qu'un demi-ton (environ 390 Hz
+// pour 2048 points a 48 kHz) : plus bas, une note s'etale sur 2 ou 3 classes.
+// Les notes graves restent reconnues par leurs harmoniques.
+const MIN_FREQUENCY: f32 = 130.0;
+const MAX_FREQUENCY: f32 = 4200.0;
+// Lissage d'une image a l'autre, et score minimum pour nommer un accord
+const SMOOTHING: f32 = 0.2;
+const MIN_SCORE: f32 = 0.8;
+// En dessous de ce niveau (dBFS d'un sinus), pas d'accord
+const MIN_LEVEL: f32 = -60.0;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Quality {
+    Major,
+    Minor,
+    Seventh,
+}
+
+impl Quality {
+    // Intervalles en demi-tons depuis la fondamentale
+    fn intervals(self) -> &'static [usize] {
+        match self {
+            Quality::Major => &[0, 4, 7],
+            Quality::Minor => &[0, 3, 7],
+            Quality::Seventh => &[0, 4, 7, 10],
+        }
+    }
+
+    fn suffix(self) -> &'static str {
+        match self {
+            Quality::Major => "",
+            Quality::Minor => "m",
+            Quality::Seventh => "7",
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Chord {
+    pub root: usize,
+    pub quality: Quality,
+    pub score: f32,
+}
+
+impl Chord {
+    pub fn name(&self) -> String {
+        format!("{}{}", NAMES[self.root], self.quality.suffix())
+    }
+
+    fn contains(&self, pitch_class: usize) -> bool {
+        self.quality
+            .intervals()
+            .iter()
+            .any(|&interval| (self.root + interval) % 12 == pitch_class)
+    }
+}
+
+// Folds the magnitude spectrum into a 12-bin chroma vector, C first, and
+// matches it against major, minor and dominant seventh chord templates.
+pub struct Chromagram {
+    // Part de l'energie de chaque bin pour chaque classe de hauteur qu'il
+    // recouvre, vide hors de la plage
+    bins: Vec<Vec<(usize, f32)>>,
+    min_energy: f32,
+    chroma: [f32; 12],
+    chord: Option<Chord>,
+}
+
+impl Chromagram {
+    pub fn new(frame_size: usize, sample_rate: f32, a4: f32) -> Self {
+        let bin_width = sample_rate / frame_size as f32;
+        let min_frequency = MIN_FREQUENCY.max(bin_width / (2f32.powf(1.0 / 12.0) - 1.0));
+        let note = |frequency: f32| 69.0 + 12.0 * (frequency / a4).log2();
+        let bins = (0..=frame_size / 2)
+            .map(|bin| {
+                let frequency = bin as f32 * bin_width;
+                if !(min_frequency..=MAX_FREQUENCY).contains(&frequency) {
+                    return Vec::new();
+                }
+                // Le bin couvre [f - w/2, f + w/2], reparti au prorata des demi-tons recouverts
+                let low = note(frequency - bin_width / 2.0);
+                let high = note(frequency + bin_width / 2.0);
+                (low.round() as i32..=high.round() as i32)
+                    .filter_map(|semitone| {
+                        let semitone_low = semitone as f32 - 0.5;
+                        let overlap = high.min(semitone_low + 1.0) - low.max(semitone_low);
+                        (overlap > 0.0)
+                            .then(|| (semitone.rem_euclid(12) as usize, overlap / (high - low)))
+                    })
+                    .collect()
+            })
+            .collect();
+        // Magnitude d'un sinus sous une fenetre de Hann : amplitude * N / 4
+        let min_magnitude = 10f32.powf(MIN_LEVEL / 20.0) * frame_size as f32 / 4.0;
+        Chromagram {
+            bins,
+            min_energy: min_magnitude * min_magnitude,
+            chroma: [0.0; 12],
+            chord: None,
+        }
+    }
+
+    pub fn chord(&self) -> Option<Chord> {
+        self.chord
+    }
+
+    pub fn update(&mut self, magnitudes: &[f32]) {
+        let mut folded = [0.0f32; 12];
+        for (&magnitude, shares) in magnitudes.iter().zip(&self.bins) {
+            for &(pitch_class, share) in shares {
+                folded[pitch_class] += share * magnitude * magnitude;
+            }
+        }
+        let energy: f32 = folded.iter().sum();
+        // Normalise par la classe la plus forte, puis lisse
+        let strongest = folded.iter().copied().fold(0.0, f32::max);
+        for (value, new) in self.chroma.iter_mut().zip(folded) {
+            let new = if strongest > 0.0 {
+                new / strongest
+            } else {
+                0.0
+            };
+            *value += SMOOTHING * (new - *value);
+        }
+
+        self.chord = if energy < self.min_energy {
+            None
+        } else {
+            self.best_chord().filter(|chord| chord.score >= MIN_SCORE)
+        };
+    }
+
+    // Cosine similarity with each of the 36 binary templates.
+    fn best_chord(&self) -> Option<Chord> {
+        let norm = self.chroma.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if norm <= 0.0 {
+            return None;
+        }
+        [Quality::Major, Quality::Minor, Quality::Seventh]
+            .into_iter()
+            .flat_map(|quality| (0..12).map(move |root| (root, quality)))
+            .map(|(root, quality)| {
+                let intervals = quality.intervals();
+                let dot: f32 = intervals
+                    .iter()
+                    .map(|&interval| self.chroma[(root + interval) % 12])
+                    .sum();
+                Chord {
+                    root,
+                    quality,
+                    score: dot / (norm * (intervals.len() as f32).sqrt()),
+                }
+            })
+            .max_by(|a, b| a.score.total_cmp(&b.score))
+    }
+
+    // Pitch-class wheel, C at the top and semitones clockwise. Each dot grows
+    // with its chroma value; the notes of the recognized chord are joined.
+    pub fn draw(&self, width: f64, height: f64, c: Context, g: &mut G2d) {
+        let radius = width.min(height) * 0.38;
+        let (center_x, center_y) = (width / 2.0, height / 2.0);
+        let position = |pitch_class: usize| {
+            let angle = pitch_class as f64 / 12.0 * std::f64::consts::TAU;
+            [
+                center_x + radius * angle.sin(),
+                center_y - radius * angle.cos(),
+            ]
+        };
+
+        if let Some(chord) = self.chord {
+            let tones: Vec<[f64; 2]> = chord
+                .quality
+                .intervals()
+                .iter()
+                .map(|&interval| position((chord.root + interval) % 12))
+                .collect();
+            for (i, &[x1, y1]) in tones.iter().enumerate() {
+                let [x2, y2] = tones[(i + 1) % tones.len()];
+                line([0.0, 0.8, 0.4, 0.8], 2.0, [x1, y1, x2, y2], c.transform, g);
+            }
+        }
+
+        for (pitch_class, &value) in self.chroma.iter().enumerate() {
+            let [x, y] = position(pitch_class);
+            let size = 6.0 + 24.0 * value as f64;
+            let color = match self.chord {
+                Some(chord) if chord.contains(pitch_class) => [0.0, 1.0, 0.5, 1.0],
+                _ => {
+                    let level = 0.25 + 0.6 * value;
+                    [level, level, level, 1.0]
+                }
+            };
+            ellipse(
+                color,
+                [x - size / 2.0, y - size / 2.0, size, size],
+                c.transform,
+                g,
+            );
+        }
+    }
+}
+
+// Texte de la barre de titre
+pub fn title(chord: Option<Chord>) -> String {
+    match chord {
+        Some(chord) => format!("Chords - {} ({:.2})", chord.name(), chord.score),
+        None => String::from("Chords - ..."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::{Analyzer, FRAME_SIZE, HOP_SIZE};
+
+    // Notes de 4 harmoniques en 1/h, comme un instrument, dans le registre
+    // ou un bin couvre plusieurs demi-tons
+    fn recognize(frequencies: &[f32]) -> Option<Chord> {
+        let sample_rate = 48000.0;
+        let mut analyzer = Analyzer::new(FRAME_SIZE, sample_rate);
+        let mut chromagram = Chromagram::new(FRAME_SIZE, sample_rate, 440.0);
+        let mut hop = vec![0.0; HOP_SIZE];
+        for block in 0..40 {
+            for (i, sample) in hop.iter_mut().enumerate() {
+                let t = (block * HOP_SIZE + i) as f32 / sample_rate;
+                *sample = frequencies
+                    .iter()
+                    .flat_map(|&f| (1..=4).map(move |h| (f, h as f32)))
+                    .map(|(f, h)| 0.05 / h * (std::f32::consts::TAU * f * h * t).sin())
+                    .sum();
+            }
+            analyzer.push(&hop);
+            chromagram.update(analyzer.magnitudes());
+        }
+        chromagram.chord()
+    }
+
+    #[test]
+    fn recognizes_c_major() {
+        let chord = recognize(&[261.63, 329.63, 392.0]).unwrap();
+        assert_eq!(chord.name(), "C");
+    }
+
+    #[test]
+    fn recognizes_a_minor() {
+        let chord = recognize(&[220.0, 261.63, 329.63]).unwrap();
+        assert_eq!(chord.name(), "Am");
+    }
+
+    #[test]
+    fn bins_share_their_energy() {
+        let chromagram = Chromagram::new(FRAME_SIZE, 48000.0, 440.0);
+        // Rien sous le plancher, et chaque bin se repartit entierement
+        assert!(chromagram.bins[10].is_empty());
+        for shares in chromagram.bins.iter().filter(|shares| !shares.is_empty()) {
+            let total: f32 = shares.iter().map(|&(_, share)| share).sum();
+            assert!((total - 1.0).abs() < 1e-4, "{}", total);
+        }
+    }
+}
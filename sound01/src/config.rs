@@ -0,0 +1,18 @@
+use std::fs;
+
+pub const CONFIG_FILE: &str = "sound01.cfg";
+
+// Reads `key=value` lines, skipping blank lines and `#` comments. A missing
+// file is the same as an empty one.
+pub fn load_config(path: &str) -> Vec<String> {
+    fs::read_to_string(path)
+        .map(|content| {
+            content
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
@@ -0,0 +1,416 @@
+use crate::analysis::MIN_DECIBELS;
+use crate::pipeline::PipelineStats;
+use piston_window::*;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::atomic::Ordering;
+
+// Un echantillon a pleine echelle, ou presque, compte comme ecrete
+const CLIP_LEVEL: f32 = 0.999;
+// Composante continue moyennee sur environ une seconde ; alerte au-dessus de 1 %
+const DC_SECONDS: f64 = 1.0;
+const DC_WARNING: f32 = 0.01;
+// Le plancher de bruit suit le minimum du niveau et remonte de 1 dB par seconde
+const FLOOR_RISE: f32 = 1.0;
+// Au plus une alerte d'ecretage par canal et par seconde
+const CLIP_LOG_SECONDS: f64 = 1.0;
+// Un ecretage reste affiche une seconde
+const CLIP_DISPLAY_SECONDS: f64 = 1.0;
+
+#[derive(Clone, Copy, Default)]
+pub struct ChannelReading {
+    pub clipped: u64,
+    pub clipping: bool,
+    pub dc: f32,
+    pub noise_floor: f32,
+}
+
+// Valeurs publiees pour la vue et la barre de titre
+#[derive(Clone, Default)]
+pub struct DiagnosticsReading {
+    pub channels: Vec<ChannelReading>,
+    // Blocs de silence numerique (tous les echantillons a zero)
+    pub dropouts: u64,
+    pub silent: bool,
+    // Ecarts anormaux entre les horodatages des callbacks
+    pub gaps: u64,
+    pub longest_gap_ms: f64,
+    pub dropped_frames: u64,
+}
+
+#[derive(Clone, Copy, Default)]
+struct ChannelState {
+    dc_warned: bool,
+    // Ecretages pas encore signales, et position du dernier signalement
+    pending_clips: u64,
+    last_clip_log: Option<u64>,
+    last_clip: Option<u64>,
+}
+
+// Watches the input for the usual faults of flaky interfaces: clipped
+// samples, DC offset, blocks of digital silence and gaps between callbacks.
+// Warnings go to stderr, and to `diag_log=<file>` when given, stamped with
+// the position in the stream.
+pub struct Diagnostics {
+    channels: usize,
+    sample_rate: u32,
+    frames: u64,
+    dc_coefficient: f32,
+    reading: DiagnosticsReading,
+    states: Vec<ChannelState>,
+    silence_start: Option<u64>,
+    pipeline: (u64, u64),
+    log: Option<(String, BufWriter<File>)>,
+}
+
+impl Diagnostics {
+    pub fn from_args(args: &[String], channels: usize, sample_rate: u32) -> Result<Self, String> {
+        let log = match args
+            .iter()
+            .find(|arg| arg.starts_with("diag_log="))
+            .and_then(|arg| arg.split('=').nth(1))
+        {
+            Some(path) => {
+                let file = File::create(path)
+                    .map_err(|err| format!("Failed to create {}: {}", path, err))?;
+                Some((path.to_string(), BufWriter::new(file)))
+            }
+            None => None,
+        };
+        let mut diagnostics = Diagnostics {
+            channels: channels.max(1),
+            sample_rate,
+            frames: 0,
+            dc_coefficient: 1.0 - (-1.0 / (DC_SECONDS * sample_rate as f64)).exp() as f32,
+            reading: DiagnosticsReading::default(),
+            states: Vec::new(),
+            silence_start: None,
+            pipeline: (0, 0),
+            log,
+        };
+        diagnostics.reset();
+        Ok(diagnostics)
+    }
+
+    // Clears the counters; the DC offset and the noise floor keep tracking.
+    pub fn reset(&mut self) {
+        let previous = std::mem::take(&mut self.reading.channels);
+        self.reading = DiagnosticsReading {
+            channels: (0..self.channels)
+                .map(|channel| ChannelReading {
+                    dc: previous.get(channel).map_or(0.0, |c| c.dc),
+                    noise_floor: previous
+                        .get(channel)
+                        .map_or(MIN_DECIBELS, |c| c.noise_floor),
+                    ..ChannelReading::default()
+                })
+                .collect(),
+            silent: self.silence_start.is_some(),
+            ..DiagnosticsReading::default()
+        };
+        self.states = vec![ChannelState::default(); self.channels];
+    }
+
+    pub fn reading(&self) -> &DiagnosticsReading {
+        &self.reading
+    }
+
+    fn seconds(&self, frames: u64) -> f64 {
+        frames as f64 / self.sample_rate as f64
+    }
+
+    fn warn(&mut self, message: String) {
+        let time = self.seconds(self.frames);
+        let line = format!(
+            "[{:02}:{:02}:{:06.3}] {}",
+            (time / 3600.0) as u64,
+            (time / 60.0) as u64 % 60,
+            time % 60.0,
+            message
+        );
+        eprintln!("{}", line);
+        if let Some((path, writer)) = &mut self.log {
+            if let Err(err) = writeln!(writer, "{}", line) {
+                eprintln!("Failed to write {}: {}", path, err);
+                self.log = None;
+            }
+        }
+    }
+
+    // `hop` holds interleaved samples.
+    pub fn push(&mut self, hop: &[f32]) {
+        let frames = (hop.len() / self.channels) as u64;
+        let hop_seconds = self.seconds(frames) as f32;
+
+        // Silence numerique : un micro USB qui decroche envoie des zeros
+        let silent = hop.iter().all(|&sample| sample == 0.0);
+        match (silent, self.silence_start) {
+            (true, None) => {
+                self.silence_start = Some(self.frames);
+                self.reading.dropouts += 1;
+                self.warn(String::from("Dropout: digital silence"));
+            }
+            (false, Some(start)) => {
+                self.silence_start = None;
+                let duration = self.seconds(self.frames - start) * 1000.0;
+                self.warn(format!("Dropout ended after {:.0} ms", duration));
+            }
+            _ => {}
+        }
+        self.reading.silent = silent;
+
+        for channel in 0..self.channels {
+            let samples = hop.iter().skip(channel).step_by(self.channels);
+            let mut clipped = 0;
+            let mut power = 0.0;
+            let reading = &mut self.reading.channels[channel];
+            for &sample in samples {
+                if sample.abs() >= CLIP_LEVEL {
+                    clipped += 1;
+                }
+                reading.dc += self.dc_coefficient * (sample - reading.dc);
+                power += sample * sample;
+            }
+            reading.clipped += clipped;
+
+            // Le silence numerique ne doit pas tirer le plancher vers le bas
+            if !silent {
+                let rms = (power / frames.max(1) as f32).sqrt();
+                let level = (20.0 * rms.log10()).max(MIN_DECIBELS);
+                reading.noise_floor = if reading.noise_floor <= MIN_DECIBELS {
+                    level
+                } else {
+                    level.min(reading.noise_floor + FLOOR_RISE * hop_seconds)
+                };
+            }
+
+            let state = &mut self.states[channel];
+            if clipped > 0 {
+                state.pending_clips += clipped;
+                state.last_clip = Some(self.frames);
+            }
+            reading.clipping = state.last_clip.is_some_and(|last| {
+                self.frames - last < (CLIP_DISPLAY_SECONDS * self.sample_rate as f64) as u64
+            });
+
+            let dc = reading.dc;
+            let log_clips = state.pending_clips > 0
+                && !state.last_clip_log.is_some_and(|last| {
+                    self.frames - last < (CLIP_LOG_SECONDS * self.sample_rate as f64) as u64
+                });
+            let dc_changed = (dc.abs() > DC_WARNING) != state.dc_warned;
+            if log_clips {
+                let count = std::mem::take(&mut state.pending_clips);
+                state.last_clip_log = Some(self.frames);
+                self.warn(format!(
+                    "Clipping: {} samples on channel {}",
+                    count,
+                    channel + 1
+                ));
+            }
+            if dc_changed {
+                let state = &mut self.states[channel];
+                state.dc_warned = !state.dc_warned;
+                let message = if state.dc_warned {
+                    format!("DC offset: {:+.2} % on channel {}", dc * 100.0, channel + 1)
+                } else {
+                    format!("DC offset back to normal on channel {}", channel + 1)
+                };
+                self.warn(message);
+            }
+        }
+        self.frames += frames;
+    }
+
+    // Reports the callback gaps and ring buffer overflows counted by the pipeline.
+    pub fn check_pipeline(&mut self, stats: &PipelineStats) {
+        let dropped = stats.dropped_frames.load(Ordering::Relaxed);
+        let gaps = stats.xruns.load(Ordering::Relaxed);
+        let longest = stats.longest_gap_micros.load(Ordering::Relaxed) as f64 / 1000.0;
+        let (last_dropped, last_gaps) = self.pipeline;
+        self.pipeline = (dropped, gaps);
+        if gaps > last_gaps {
+            self.reading.gaps += gaps - last_gaps;
+            self.reading.longest_gap_ms = longest;
+            self.warn(format!(
+                "Gap between callbacks: {} more, longest {:.1} ms",
+                gaps - last_gaps,
+                longest
+            ));
+        }
+        if dropped > last_dropped {
+            self.reading.dropped_frames += dropped - last_dropped;
+            self.warn(format!(
+                "Overflow: {} frames dropped, analysis too slow",
+                dropped - last_dropped
+            ));
+        }
+    }
+
+    // Reports the clipping not logged yet and flushes the log file.
+    pub fn finish(&mut self) {
+        for channel in 0..self.channels {
+            let count = std::mem::take(&mut self.states[channel].pending_clips);
+            if count > 0 {
+                self.warn(format!(
+                    "Clipping: {} samples on channel {}",
+                    count,
+                    channel + 1
+                ));
+            }
+        }
+        if let Some((path, writer)) = &mut self.log {
+            if let Err(err) = writer.flush() {
+                eprintln!("Failed to write {}: {}", path, err);
+            }
+        }
+    }
+}
+
+// Une colonne par canal : ecretage en haut, composante continue au milieu
+// (+-5 % sur la hauteur), plancher de bruit en bas de -120 a 0 dB.
+pub fn draw(reading: &DiagnosticsReading, width: f64, height: f64, c: Context, g: &mut G2d) {
+    const GRAY: [f32; 4] = [0.15, 0.15, 0.15, 1.0];
+    const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+    let count = reading.channels.len().max(1) as f64;
+    let column = width / count;
+    let margin = column * 0.1;
+
+    for (index, channel) in reading.channels.iter().enumerate() {
+        let x = column * index as f64 + margin;
+        let w = column - 2.0 * margin;
+
+        rectangle(
+            if channel.clipping { RED } else { GRAY },
+            [x, height * 0.05, w, height * 0.1],
+            c.transform,
+            g,
+        );
+
+        let (top, bottom) = (height * 0.2, height * 0.5);
+        let middle = (top + bottom) / 2.0;
+        rectangle(GRAY, [x, top, w, bottom - top], c.transform, g);
+        let offset = (channel.dc as f64 / 0.05).clamp(-1.0, 1.0) * (bottom - top) / 2.0;
+        let color = if channel.dc.abs() > DC_WARNING {
+            [1.0, 0.6, 0.0, 1.0]
+        } else {
+            [0.2, 0.6, 1.0, 1.0]
+        };
+        rectangle(
+            color,
+            [x, middle.min(middle - offset), w, offset.abs().max(1.0)],
+            c.transform,
+            g,
+        );
+
+        let (top, bottom) = (height * 0.55, height * 0.9);
+        rectangle(GRAY, [x, top, w, bottom - top], c.transform, g);
+        let t = ((channel.noise_floor - MIN_DECIBELS) / -MIN_DECIBELS).clamp(0.0, 1.0) as f64;
+        let y = bottom - t * (bottom - top);
+        rectangle([0.0, 0.8, 0.2, 1.0], [x, y, w, bottom - y], c.transform, g);
+    }
+
+    // Bande du bas : rouge pendant un silence numerique
+    let color = if reading.silent {
+        RED
+    } else {
+        [0.0, 0.4, 0.0, 1.0]
+    };
+    rectangle(
+        color,
+        [0.0, height * 0.94, width, height * 0.04],
+        c.transform,
+        g,
+    );
+}
+
+// Texte de la barre de titre, le pire canal pour l'ecretage et la composante continue
+pub fn title(reading: &DiagnosticsReading) -> String {
+    let clipped: u64 = reading.channels.iter().map(|c| c.clipped).sum();
+    let dc = reading
+        .channels
+        .iter()
+        .map(|c| c.dc)
+        .max_by(|a, b| a.abs().total_cmp(&b.abs()))
+        .unwrap_or(0.0);
+    let floor = reading
+        .channels
+        .iter()
+        .map(|c| c.noise_floor)
+        .fold(MIN_DECIBELS, f32::max);
+    format!(
+        "Diagnostics - clipped {}, DC {:+.2} %, floor {:.0} dB, dropouts {}, gaps {} (longest {:.0} ms), overflow {}",
+        clipped,
+        dc * 100.0,
+        floor,
+        reading.dropouts,
+        reading.gaps,
+        reading.longest_gap_ms,
+        reading.dropped_frames
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_clipping_dc_and_dropouts() {
+        let mut diagnostics = Diagnostics::from_args(&[], 2, 48000).unwrap();
+        let mut hop = vec![0.0; 1024];
+        // Canal 1 : sinus ecrete, canal 2 : sinus faible avec 5 % de continu, sur 5 s
+        for block in 0..500 {
+            for (frame, pair) in hop.chunks_mut(2).enumerate() {
+                let t = (block * 512 + frame) as f32 / 48000.0;
+                pair[0] = (1.5 * (std::f32::consts::TAU * 100.0 * t).sin()).clamp(-1.0, 1.0);
+                pair[1] = 0.05 + 0.001 * (std::f32::consts::TAU * 3000.0 * t).sin();
+            }
+            diagnostics.push(&hop);
+        }
+        let reading = diagnostics.reading().clone();
+        assert!(reading.channels[0].clipped > 0);
+        assert!(reading.channels[0].clipping);
+        assert_eq!(reading.channels[1].clipped, 0);
+        assert!(
+            reading.channels[0].dc.abs() < 0.01,
+            "{}",
+            reading.channels[0].dc
+        );
+        assert!(
+            (reading.channels[1].dc - 0.05).abs() < 0.005,
+            "{}",
+            reading.channels[1].dc
+        );
+        assert_eq!(reading.dropouts, 0);
+
+        // Deux blocs de zeros : un seul decrochage
+        hop.fill(0.0);
+        diagnostics.push(&hop);
+        diagnostics.push(&hop);
+        assert!(diagnostics.reading().silent);
+        hop.fill(0.1);
+        diagnostics.push(&hop);
+        assert!(!diagnostics.reading().silent);
+        assert_eq!(diagnostics.reading().dropouts, 1);
+
+        diagnostics.reset();
+        assert_eq!(diagnostics.reading().channels[0].clipped, 0);
+        assert_eq!(diagnostics.reading().dropouts, 0);
+    }
+
+    #[test]
+    fn noise_floor_follows_the_quiet_parts() {
+        let mut diagnostics = Diagnostics::from_args(&[], 1, 48000).unwrap();
+        let mut hop = vec![0.0; 512];
+        for block in 0..100 {
+            // -40 dBFS RMS, avec un passage fort au milieu
+            let level = if (40..60).contains(&block) { 0.5 } else { 0.01 };
+            for (i, sample) in hop.iter_mut().enumerate() {
+                *sample = if i % 2 == 0 { level } else { -level };
+            }
+            diagnostics.push(&hop);
+        }
+        let floor = diagnostics.reading().channels[0].noise_floor;
+        assert!((floor + 40.0).abs() < 1.0, "{}", floor);
+    }
+}
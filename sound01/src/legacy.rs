@@ -0,0 +1,77 @@
+// The first two versions of the visualizer, kept as modes on top of the
+// shared analysis: no smoothing and no mapping table.
+use crate::shapes::RandomColor;
+use piston_window::*;
+
+// Cercle dont la taille suit le niveau, sur fond blanc
+pub struct Meter {
+    color: RandomColor,
+}
+
+impl Meter {
+    pub fn new() -> Self {
+        Meter {
+            color: RandomColor::new(),
+        }
+    }
+
+    pub fn draw(&mut self, decibels: f32, width: f64, height: f64, c: Context, g: &mut G2d) {
+        let diameter = ((decibels + 60.0).max(0.0) * 10.0) as f64;
+        let color = self.color.next();
+        clear([1.0; 4], g);
+        ellipse(
+            color,
+            [
+                width / 2.0 - diameter / 2.0,
+                height / 2.0 - diameter / 2.0,
+                diameter,
+                diameter,
+            ],
+            c.transform,
+            g,
+        );
+    }
+}
+
+// Cercle sous 200 Hz, carre jusqu'a 400 Hz, triangle au-dessus
+pub struct Legacy {
+    color: RandomColor,
+}
+
+impl Legacy {
+    pub fn new() -> Self {
+        Legacy {
+            color: RandomColor::new(),
+        }
+    }
+
+    pub fn draw(
+        &mut self,
+        decibels: f32,
+        pitch: f32,
+        width: f64,
+        height: f64,
+        c: Context,
+        g: &mut G2d,
+    ) {
+        let size = ((decibels + 60.0).max(0.0) * 20.0) as f64; // Ajuster l'échelle pour rendre la taille plus sensible aux décibels
+        let color = self.color.next();
+        let (x, y) = (width / 2.0, height / 2.0);
+        let square = [x - size / 2.0, y - size / 2.0, size, size];
+        clear([0.0; 4], g);
+
+        // Changer la forme en fonction du pitch
+        if pitch < 200.0 {
+            ellipse(color, square, c.transform, g);
+        } else if pitch < 400.0 {
+            rectangle(color, square, c.transform, g);
+        } else {
+            let points = [
+                [x, y - size / 2.0],
+                [x - size / 2.0, y + size / 2.0],
+                [x + size / 2.0, y + size / 2.0],
+            ];
+            polygon(color, &points, c.transform, g);
+        }
+    }
+}
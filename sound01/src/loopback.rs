@@ -0,0 +1,298 @@
+use crate::analysis::HOP_SIZE;
+use crate::device;
+use crate::generator::{Generator, Signal};
+use crate::pipeline;
+use cpal::traits::{DeviceTrait, StreamTrait};
+use cpal::{Device, SupportedStreamConfig};
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use std::io::Write;
+
+// Balayage de mesure, precede d'un silence et suivi d'une marge pour la latence
+const SWEEP_START: f64 = 20.0;
+const SWEEP_END: f64 = 20000.0;
+const SWEEP_SECONDS: f64 = 3.0;
+const LEAD_SECONDS: f64 = 0.5;
+const MAX_LATENCY_SECONDS: f64 = 1.0;
+// Marge au-dela de la duree de l'enregistrement avant d'abandonner
+const TIMEOUT_MARGIN_SECONDS: f64 = 3.0;
+// En dessous, le balayage n'a pas ete retrouve dans l'enregistrement
+const MIN_CORRELATION: f32 = 0.1;
+// Frequences centrales nominales des tiers d'octave, normalisees sur 1 kHz
+const BANDS: [f64; 28] = [
+    31.5, 40.0, 50.0, 63.0, 80.0, 100.0, 125.0, 160.0, 200.0, 250.0, 315.0, 400.0, 500.0, 630.0,
+    800.0, 1000.0, 1250.0, 1600.0, 2000.0, 2500.0, 3150.0, 4000.0, 5000.0, 6300.0, 8000.0, 10000.0,
+    12500.0, 16000.0,
+];
+const REFERENCE_BAND: f64 = 1000.0;
+
+pub struct Measurement {
+    pub latency_samples: usize,
+    pub correlation: f32,
+    // (frequence centrale, gain en dB relatif a 1 kHz)
+    pub response: Vec<(f64, f64)>,
+}
+
+fn spectrum(signal: &[f32], size: usize, planner: &mut FftPlanner<f32>) -> Vec<Complex<f32>> {
+    let mut buffer: Vec<Complex<f32>> = signal.iter().map(|&x| Complex::new(x, 0.0)).collect();
+    buffer.resize(size, Complex::new(0.0, 0.0));
+    planner.plan_fft_forward(size).process(&mut buffer);
+    buffer
+}
+
+// Finds the delay of `played` inside `recorded` by cross-correlation, then
+// compares their spectra once aligned: the ratio is the response of the
+// output, cable and input chain.
+pub fn measure(played: &[f32], recorded: &[f32], sample_rate: u32) -> Result<Measurement, String> {
+    if played.is_empty() || recorded.len() < played.len() {
+        return Err(String::from("Recording shorter than the test signal"));
+    }
+    let size = (played.len() + recorded.len()).next_power_of_two();
+    let mut planner = FftPlanner::new();
+    let x = spectrum(played, size, &mut planner);
+    let y = spectrum(recorded, size, &mut planner);
+
+    // Correlation croisee : IFFT de Y * conj(X)
+    let mut correlation: Vec<Complex<f32>> = y.iter().zip(&x).map(|(y, x)| y * x.conj()).collect();
+    planner.plan_fft_inverse(size).process(&mut correlation);
+    let lags = recorded.len().saturating_sub(played.len()) + 1;
+    // Au moins un decalage, l'enregistrement n'etant pas plus court
+    let (latency_samples, peak) = correlation[..lags]
+        .iter()
+        .map(|c| c.re / size as f32)
+        .enumerate()
+        .max_by(|a, b| a.1.abs().total_cmp(&b.1.abs()))
+        .unwrap();
+
+    let energy = |signal: &[f32]| signal.iter().map(|v| v * v).sum::<f32>();
+    let aligned = &recorded[latency_samples..latency_samples + played.len()];
+    let norm = (energy(played) * energy(aligned)).sqrt();
+    let correlation = if norm > 0.0 { peak.abs() / norm } else { 0.0 };
+    if correlation < MIN_CORRELATION {
+        return Err(format!(
+            "Test signal not found in the input (correlation {:.2}), check the loopback cable and levels",
+            correlation
+        ));
+    }
+
+    let size = played.len().next_power_of_two();
+    let x = spectrum(played, size, &mut planner);
+    let y = spectrum(aligned, size, &mut planner);
+    let bin_width = sample_rate as f64 / size as f64;
+    let band_gain = |center: f64| {
+        let edge = 2f64.powf(1.0 / 6.0);
+        let low = ((center / edge) / bin_width).ceil() as usize;
+        let high = (((center * edge) / bin_width).floor() as usize).min(size / 2);
+        let (mut input, mut output) = (0.0, 0.0);
+        for bin in low..=high {
+            input += x[bin].norm_sqr() as f64;
+            output += y[bin].norm_sqr() as f64;
+        }
+        (input > 0.0 && output > 0.0).then(|| 10.0 * (output / input).log10())
+    };
+
+    let nyquist = sample_rate as f64 / 2.0;
+    let reference =
+        band_gain(REFERENCE_BAND).ok_or_else(|| String::from("No signal in the 1 kHz band"))?;
+    let response = BANDS
+        .into_iter()
+        .filter(|&center| center * 2f64.powf(1.0 / 6.0) < nyquist.min(SWEEP_END))
+        .filter_map(|center| band_gain(center).map(|gain| (center, gain - reference)))
+        .collect();
+
+    Ok(Measurement {
+        latency_samples,
+        correlation,
+        response,
+    })
+}
+
+// Plays a log sweep through the output while recording the input, then
+// prints the round-trip latency and the frequency response. `out` gets the
+// response as CSV.
+pub fn run(
+    input: (Device, SupportedStreamConfig),
+    output: (Device, SupportedStreamConfig),
+    level: f32,
+    out: Option<&mut dyn Write>,
+) -> Result<(), String> {
+    let (input_device, input_config) = input;
+    let (output_device, output_config) = output;
+    let sample_rate = input_config.sample_rate().0;
+    let input_channels = input_config.channels() as usize;
+    let output_channels = output_config.channels() as usize;
+
+    let seconds = |s: f64| (s * sample_rate as f64) as usize;
+    let sweep = Signal::Sweep {
+        start: SWEEP_START,
+        end: SWEEP_END.min(sample_rate as f64 * 0.45),
+        seconds: SWEEP_SECONDS,
+    };
+    let mut generator = Generator::new(sweep, sample_rate, level);
+    let mut played = vec![0.0; seconds(LEAD_SECONDS)];
+    played.extend((0..seconds(SWEEP_SECONDS)).map(|_| generator.next_sample()));
+    let total = played.len() + seconds(MAX_LATENCY_SECONDS);
+
+    let (mut sender, mut reader, stats) = pipeline::sample_channel(
+        total * input_channels,
+        HOP_SIZE * input_channels,
+        input_channels,
+        sample_rate,
+    );
+    let input_stream = device::build_input_stream(
+        &input_device,
+        &input_config,
+        move |data, info| sender.push(data, Some(info)),
+        |err| eprintln!("Error: {:?}", err),
+    )?;
+
+    let playback = played.clone();
+    let mut position = 0;
+    let output_stream = device::build_output_stream(
+        &output_device,
+        &output_config,
+        move |data| {
+            for frame in data.chunks_mut(output_channels) {
+                frame.fill(playback.get(position).copied().unwrap_or(0.0));
+                position += 1;
+            }
+        },
+        |err| eprintln!("Error: {:?}", err),
+    )?;
+
+    println!(
+        "Loopback: {} -> {} at {} Hz, {:.1} s sweep",
+        output_device.name().unwrap_or_default(),
+        input_device.name().unwrap_or_default(),
+        sample_rate,
+        SWEEP_SECONDS
+    );
+    input_stream
+        .play()
+        .map_err(|err| format!("Failed to start input stream: {}", err))?;
+    output_stream
+        .play()
+        .map_err(|err| format!("Failed to start output stream: {}", err))?;
+
+    // Une entree qui ne livre plus rien ne doit pas bloquer indefiniment
+    let deadline = std::time::Instant::now()
+        + std::time::Duration::from_secs_f64(
+            total as f64 / sample_rate as f64 + TIMEOUT_MARGIN_SECONDS,
+        );
+    let mut recorded = Vec::with_capacity(total);
+    while recorded.len() < total {
+        if std::time::Instant::now() > deadline {
+            return Err(format!(
+                "Timed out waiting for the input: {:.1} of {:.1} s recorded",
+                recorded.len() as f64 / sample_rate as f64,
+                total as f64 / sample_rate as f64
+            ));
+        }
+        match reader.next_frame() {
+            Some(hop) => recorded.extend(
+                hop.chunks(input_channels)
+                    .map(|frame| frame.iter().sum::<f32>() / input_channels as f32),
+            ),
+            None => std::thread::sleep(std::time::Duration::from_millis(5)),
+        }
+    }
+    drop(output_stream);
+    drop(input_stream);
+    stats.report(&mut (0, 0));
+
+    let measurement = measure(&played, &recorded, sample_rate)?;
+    println!(
+        "Latency: {} samples, {:.2} ms (correlation {:.2})",
+        measurement.latency_samples,
+        measurement.latency_samples as f64 * 1000.0 / sample_rate as f64,
+        measurement.correlation
+    );
+    println!("Frequency response, relative to 1 kHz:");
+    for (center, gain) in &measurement.response {
+        println!("  {:>7} Hz {:>+6.1} dB", center, gain);
+    }
+
+    if let Some(out) = out {
+        let error = |err: std::io::Error| format!("Failed to write response: {}", err);
+        writeln!(out, "frequency,gain_db").map_err(error)?;
+        for (center, gain) in &measurement.response {
+            writeln!(out, "{:.1},{:.2}", center, gain).map_err(error)?;
+        }
+        out.flush().map_err(error)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sweep(sample_rate: u32) -> Vec<f32> {
+        let signal = Signal::Sweep {
+            start: 20.0,
+            end: 20000.0,
+            seconds: 1.0,
+        };
+        let mut generator = Generator::new(signal, sample_rate, -6.0);
+        (0..sample_rate).map(|_| generator.next_sample()).collect()
+    }
+
+    #[test]
+    fn finds_the_delay_of_a_flat_chain() {
+        let played = sweep(48000);
+        let mut recorded = vec![0.0; 1234];
+        recorded.extend(played.iter().map(|v| v * 0.5));
+        recorded.resize(played.len() + 4800, 0.0);
+
+        let measurement = measure(&played, &recorded, 48000).unwrap();
+        assert_eq!(measurement.latency_samples, 1234);
+        assert!(measurement.correlation > 0.99);
+        for (center, gain) in &measurement.response {
+            assert!(gain.abs() < 0.5, "{} Hz: {} dB", center, gain);
+        }
+    }
+
+    #[test]
+    fn measures_a_low_pass_response() {
+        let played = sweep(48000);
+        let mut recorded = vec![0.0; 300];
+        // Passe-bas du premier ordre a environ 1.6 kHz
+        let coefficient = 0.2;
+        let mut state = 0.0;
+        recorded.extend(played.iter().map(|&v| {
+            state += coefficient * (v - state);
+            state
+        }));
+        recorded.resize(played.len() + 4800, 0.0);
+
+        let measurement = measure(&played, &recorded, 48000).unwrap();
+        assert!((290..=310).contains(&measurement.latency_samples));
+        let gain_at = |frequency: f64| {
+            measurement
+                .response
+                .iter()
+                .find(|(center, _)| *center == frequency)
+                .map(|(_, gain)| *gain)
+                .unwrap()
+        };
+        assert!(gain_at(100.0) > gain_at(1000.0));
+        assert!(gain_at(10000.0) < -10.0);
+    }
+
+    #[test]
+    fn rejects_a_recording_without_the_signal() {
+        let played = sweep(48000);
+        let recorded = vec![0.0; played.len() + 4800];
+        assert!(measure(&played, &recorded, 48000).is_err());
+    }
+
+    #[test]
+    fn rejects_a_recording_shorter_than_the_signal() {
+        let played = sweep(48000);
+        let recorded = played[..played.len() / 2].to_vec();
+        assert_eq!(
+            measure(&played, &recorded, 48000).err().as_deref(),
+            Some("Recording shorter than the test signal")
+        );
+    }
+}
@@ -0,0 +1,492 @@
+use piston_window::*;
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+// EBU R128 / ITU-R BS.1770 : blocs de 100 ms, fenetres de 400 ms et 3 s
+const BLOCK_SECONDS: f64 = 0.1;
+const MOMENTARY_BLOCKS: usize = 4;
+const SHORT_TERM_BLOCKS: usize = 30;
+// La LRA utilise une mesure court terme par seconde
+const RANGE_HOP_BLOCKS: usize = 10;
+
+const ABSOLUTE_GATE: f64 = -70.0;
+const RELATIVE_GATE: f64 = -10.0;
+const RANGE_RELATIVE_GATE: f64 = -20.0;
+const RANGE_LOW_PERCENTILE: f64 = 0.10;
+const RANGE_HIGH_PERCENTILE: f64 = 0.95;
+
+// Facteur de sur-echantillonnage et longueur du filtre d'interpolation pour le true peak
+const OVERSAMPLING: usize = 4;
+const TAPS_PER_PHASE: usize = 16;
+
+// Valeurs publiees par le thread d'analyse. None tant qu'il n'y a pas de quoi mesurer,
+// ou sur du silence.
+#[derive(Clone, Copy, Default)]
+pub struct LoudnessReading {
+    pub momentary: Option<f64>,
+    pub short_term: Option<f64>,
+    pub integrated: Option<f64>,
+    // Low and high ends of the loudness range, LRA = high - low.
+    pub range: Option<(f64, f64)>,
+    pub true_peak: Option<f64>,
+}
+
+// Transposed direct form II, one state per channel.
+#[derive(Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+}
+
+impl Biquad {
+    fn process(&self, state: &mut [f64; 2], x: f64) -> f64 {
+        let y = self.b[0] * x + state[0];
+        state[0] = self.b[1] * x - self.a[0] * y + state[1];
+        state[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+// K-weighting for any sample rate: the high shelf then the high pass of
+// BS.1770, designed from their analog prototypes.
+fn k_weighting(sample_rate: f64) -> [Biquad; 2] {
+    let k = (PI * 1681.974450955533 / sample_rate).tan();
+    let q = 0.7071752369554196;
+    let vh = 10f64.powf(3.999843853973347 / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b: [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    };
+
+    let k = (PI * 38.13547087602444 / sample_rate).tan();
+    let q = 0.5003270373238773;
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad {
+        b: [1.0, -2.0, 1.0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    };
+    [shelf, high_pass]
+}
+
+fn to_lufs(energy: f64) -> Option<f64> {
+    (energy > 0.0).then(|| -0.691 + 10.0 * energy.log10())
+}
+
+fn to_energy(lufs: f64) -> f64 {
+    10f64.powf((lufs + 0.691) / 10.0)
+}
+
+fn mean(values: impl Iterator<Item = f64>) -> f64 {
+    let (sum, count) = values.fold((0.0, 0usize), |(sum, count), v| (sum + v, count + 1));
+    if count == 0 {
+        0.0
+    } else {
+        sum / count as f64
+    }
+}
+
+// Loudness of interleaved samples: momentary, short-term, integrated and range.
+pub struct Loudness {
+    channels: usize,
+    sample_rate: u32,
+    weights: Vec<f64>,
+    filters: [Biquad; 2],
+    states: Vec<[[f64; 2]; 2]>,
+    block_size: usize,
+    block_filled: usize,
+    block_energy: f64,
+    total_blocks: usize,
+    next_range_block: usize,
+    // Energie moyenne ponderee des derniers blocs de 100 ms
+    blocks: VecDeque<f64>,
+    // Energie des fenetres de 400 ms (pour l'integree) et de 3 s (pour la LRA)
+    gating_blocks: Vec<f64>,
+    short_term_blocks: Vec<f64>,
+}
+
+impl Loudness {
+    pub fn new(channels: usize, sample_rate: u32) -> Self {
+        let channels = channels.max(1);
+        // En 5.1 (L R C LFE Ls Rs), le LFE est ignore et les surround comptent plus
+        let weights = (0..channels)
+            .map(|channel| match (channels, channel) {
+                (6, 3) => 0.0,
+                (6, 4) | (6, 5) => 1.41,
+                _ => 1.0,
+            })
+            .collect();
+        Loudness {
+            channels,
+            sample_rate,
+            weights,
+            filters: k_weighting(sample_rate as f64),
+            states: vec![[[0.0; 2]; 2]; channels],
+            block_size: ((sample_rate as f64 * BLOCK_SECONDS) as usize).max(1),
+            block_filled: 0,
+            block_energy: 0.0,
+            total_blocks: 0,
+            next_range_block: SHORT_TERM_BLOCKS,
+            blocks: VecDeque::with_capacity(SHORT_TERM_BLOCKS + 1),
+            gating_blocks: Vec::new(),
+            short_term_blocks: Vec::new(),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Loudness::new(self.channels, self.sample_rate);
+    }
+
+    pub fn push(&mut self, data: &[f32]) {
+        for frame in data.chunks_exact(self.channels) {
+            for (channel, &sample) in frame.iter().enumerate() {
+                let state = &mut self.states[channel];
+                let y = self.filters[0].process(&mut state[0], sample as f64);
+                let y = self.filters[1].process(&mut state[1], y);
+                self.block_energy += self.weights[channel] * y * y;
+            }
+            self.block_filled += 1;
+            if self.block_filled == self.block_size {
+                self.finish_block();
+            }
+        }
+    }
+
+    fn finish_block(&mut self) {
+        self.blocks
+            .push_back(self.block_energy / self.block_size as f64);
+        if self.blocks.len() > SHORT_TERM_BLOCKS {
+            self.blocks.pop_front();
+        }
+        self.block_filled = 0;
+        self.block_energy = 0.0;
+        self.total_blocks += 1;
+
+        // Fenetres de 400 ms avec 75 % de recouvrement
+        if let Some(energy) = self.window_energy(MOMENTARY_BLOCKS) {
+            self.gating_blocks.push(energy);
+        }
+        if self.total_blocks == self.next_range_block {
+            self.next_range_block += RANGE_HOP_BLOCKS;
+            if let Some(energy) = self.window_energy(SHORT_TERM_BLOCKS) {
+                self.short_term_blocks.push(energy);
+            }
+        }
+    }
+
+    // Mean energy of the last `count` blocks, once there are enough of them.
+    fn window_energy(&self, count: usize) -> Option<f64> {
+        (self.blocks.len() >= count).then(|| mean(self.blocks.iter().rev().take(count).copied()))
+    }
+
+    pub fn momentary(&self) -> Option<f64> {
+        self.window_energy(MOMENTARY_BLOCKS).and_then(to_lufs)
+    }
+
+    pub fn short_term(&self) -> Option<f64> {
+        self.window_energy(SHORT_TERM_BLOCKS).and_then(to_lufs)
+    }
+
+    // Two gates: blocks under -70 LUFS are dropped, then those more than
+    // 10 LU under the loudness of the remaining ones.
+    pub fn integrated(&self) -> Option<f64> {
+        let absolute = to_energy(ABSOLUTE_GATE);
+        let above = || self.gating_blocks.iter().copied().filter(|&e| e > absolute);
+        let relative = to_energy(to_lufs(mean(above()))? + RELATIVE_GATE);
+        to_lufs(mean(above().filter(|&e| e > relative)))
+    }
+
+    // Spread between the 10th and 95th percentiles of the short-term loudness,
+    // after an absolute gate and a relative gate 20 LU under their mean.
+    pub fn range(&self) -> Option<(f64, f64)> {
+        let absolute = to_energy(ABSOLUTE_GATE);
+        let above = || {
+            self.short_term_blocks
+                .iter()
+                .copied()
+                .filter(|&e| e > absolute)
+        };
+        let relative = to_energy(to_lufs(mean(above()))? + RANGE_RELATIVE_GATE);
+        let mut values: Vec<f64> = above()
+            .filter(|&e| e > relative)
+            .filter_map(to_lufs)
+            .collect();
+        if values.is_empty() {
+            return None;
+        }
+        values.sort_by(f64::total_cmp);
+        let percentile = |p: f64| values[((values.len() - 1) as f64 * p).round() as usize];
+        Some((
+            percentile(RANGE_LOW_PERCENTILE),
+            percentile(RANGE_HIGH_PERCENTILE),
+        ))
+    }
+}
+
+// Maximum of the signal oversampled 4 times, per BS.1770 Annex 2, over all channels.
+pub struct TruePeak {
+    channels: usize,
+    // Une phase du filtre polyphase par position intermediaire
+    phases: [[f32; TAPS_PER_PHASE]; OVERSAMPLING],
+    history: Vec<[f32; TAPS_PER_PHASE]>,
+    peak: f32,
+}
+
+impl TruePeak {
+    pub fn new(channels: usize) -> Self {
+        // Sinus cardinal fenetre (Blackman), coupure a la frequence de Nyquist d'origine
+        let length = TAPS_PER_PHASE * OVERSAMPLING;
+        let center = (length - 1) as f64 / 2.0;
+        let mut phases = [[0.0; TAPS_PER_PHASE]; OVERSAMPLING];
+        for n in 0..length {
+            let x = (n as f64 - center) / OVERSAMPLING as f64;
+            let sinc = if x == 0.0 {
+                1.0
+            } else {
+                (PI * x).sin() / (PI * x)
+            };
+            let t = n as f64 / (length - 1) as f64;
+            let window = 0.42 - 0.5 * (2.0 * PI * t).cos() + 0.08 * (4.0 * PI * t).cos();
+            phases[n % OVERSAMPLING][n / OVERSAMPLING] = (sinc * window) as f32;
+        }
+        // Gain unitaire pour chaque phase
+        for phase in phases.iter_mut() {
+            let sum: f32 = phase.iter().sum();
+            phase.iter_mut().for_each(|tap| *tap /= sum);
+        }
+        let channels = channels.max(1);
+        TruePeak {
+            channels,
+            phases,
+            history: vec![[0.0; TAPS_PER_PHASE]; channels],
+            peak: 0.0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.peak = 0.0;
+    }
+
+    pub fn push(&mut self, data: &[f32]) {
+        for frame in data.chunks_exact(self.channels) {
+            for (history, &sample) in self.history.iter_mut().zip(frame) {
+                history.copy_within(1.., 0);
+                history[TAPS_PER_PHASE - 1] = sample;
+                self.peak = self.peak.max(sample.abs());
+                for phase in &self.phases {
+                    let value: f32 = phase.iter().zip(history.iter()).map(|(&h, &x)| h * x).sum();
+                    self.peak = self.peak.max(value.abs());
+                }
+            }
+        }
+    }
+
+    pub fn dbtp(&self) -> Option<f64> {
+        (self.peak > 0.0).then(|| 20.0 * (self.peak as f64).log10())
+    }
+}
+
+// Echelle du meter et niveau cible
+const METER_MIN: f64 = -60.0;
+const METER_MAX: f64 = 0.0;
+const TARGET_LUFS: f64 = -23.0;
+const TRUE_PEAK_LIMIT: f64 = -1.0;
+
+// Bars for momentary, short-term and integrated loudness, the loudness range
+// as a band beside them, and the true peak, on a -60..0 scale with the -23 LUFS target.
+pub fn draw(reading: &LoudnessReading, width: f64, height: f64, c: Context, g: &mut G2d) {
+    let top = height * 0.05;
+    let bottom = height * 0.95;
+    let y = |level: f64| {
+        let t = ((level - METER_MIN) / (METER_MAX - METER_MIN)).clamp(0.0, 1.0);
+        bottom - t * (bottom - top)
+    };
+    let column = width / 6.0;
+
+    // Graduations tous les 10 dB
+    for level in (METER_MIN as i32..=METER_MAX as i32).step_by(10) {
+        let y = y(level as f64);
+        line(
+            [0.2, 0.2, 0.2, 1.0],
+            0.5,
+            [0.0, y, width, y],
+            c.transform,
+            g,
+        );
+    }
+
+    let bar = |index: usize, level: Option<f64>, color: [f32; 4], g: &mut G2d| {
+        let x = column * (index as f64 + 0.5);
+        rectangle(
+            [0.15, 0.15, 0.15, 1.0],
+            [x, top, column * 0.8, bottom - top],
+            c.transform,
+            g,
+        );
+        if let Some(level) = level {
+            let y = y(level);
+            rectangle(color, [x, y, column * 0.8, bottom - y], c.transform, g);
+        }
+    };
+    let color = |level: Option<f64>| match level {
+        Some(level) if level > TARGET_LUFS + 1.0 => [1.0, 0.5, 0.0, 1.0],
+        _ => [0.0, 0.8, 0.2, 1.0],
+    };
+    bar(0, reading.momentary, color(reading.momentary), g);
+    bar(1, reading.short_term, color(reading.short_term), g);
+    bar(2, reading.integrated, [0.2, 0.6, 1.0, 1.0], g);
+    let peak_color = match reading.true_peak {
+        Some(peak) if peak > TRUE_PEAK_LIMIT => [1.0, 0.0, 0.0, 1.0],
+        _ => [0.8, 0.8, 0.8, 1.0],
+    };
+    bar(4, reading.true_peak, peak_color, g);
+
+    if let Some((low, high)) = reading.range {
+        let x = column * 3.5;
+        rectangle(
+            [0.6, 0.3, 1.0, 0.8],
+            [x, y(high), column * 0.8, y(low) - y(high)],
+            c.transform,
+            g,
+        );
+    }
+
+    let target = y(TARGET_LUFS);
+    line(
+        [1.0, 1.0, 1.0, 1.0],
+        1.0,
+        [0.0, target, column * 3.4, target],
+        c.transform,
+        g,
+    );
+}
+
+// Texte de la barre de titre
+pub fn title(reading: &LoudnessReading) -> String {
+    let value = |level: Option<f64>| level.map_or(String::from("-inf"), |l| format!("{:.1}", l));
+    format!(
+        "Loudness - M {} S {} I {} LUFS, LRA {} LU, TP {} dBTP",
+        value(reading.momentary),
+        value(reading.short_term),
+        value(reading.integrated),
+        value(reading.range.map(|(low, high)| high - low)),
+        value(reading.true_peak)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    // Sinus stereo de 1 kHz, meme signal sur les deux canaux, par segments (dBFS, secondes)
+    fn stereo_sine(segments: &[(f64, f64)]) -> Vec<f32> {
+        let mut out = Vec::new();
+        let mut n = 0u64;
+        for &(level, seconds) in segments {
+            let amplitude = 10f64.powf(level / 20.0);
+            for _ in 0..(seconds * SAMPLE_RATE as f64).round() as u64 {
+                let t = n as f64 / SAMPLE_RATE as f64;
+                let sample = (amplitude * (2.0 * PI * 1000.0 * t).sin()) as f32;
+                out.extend_from_slice(&[sample, sample]);
+                n += 1;
+            }
+        }
+        out
+    }
+
+    fn measure(segments: &[(f64, f64)]) -> Loudness {
+        let mut loudness = Loudness::new(2, SAMPLE_RATE);
+        for chunk in stereo_sine(segments).chunks(1024) {
+            loudness.push(chunk);
+        }
+        loudness
+    }
+
+    fn assert_near(actual: Option<f64>, expected: f64, tolerance: f64) {
+        let actual = actual.expect("no measure");
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    // EBU Tech 3341, cas 1 et 2
+    #[test]
+    fn steady_sine_reads_its_level() {
+        for level in [-23.0, -33.0] {
+            let loudness = measure(&[(level, 20.0)]);
+            assert_near(loudness.momentary(), level, 0.1);
+            assert_near(loudness.short_term(), level, 0.1);
+            assert_near(loudness.integrated(), level, 0.1);
+        }
+    }
+
+    // EBU Tech 3341, cas 3 a 5 : les passages calmes sont ecartes par le gating
+    #[test]
+    fn gating_ignores_quiet_passages() {
+        let cases: [&[(f64, f64)]; 3] = [
+            &[(-36.0, 10.0), (-23.0, 60.0), (-36.0, 10.0)],
+            &[
+                (-72.0, 10.0),
+                (-36.0, 10.0),
+                (-23.0, 60.0),
+                (-36.0, 10.0),
+                (-72.0, 10.0),
+            ],
+            &[(-26.0, 20.0), (-20.0, 20.1), (-26.0, 20.0)],
+        ];
+        for segments in cases {
+            assert_near(measure(segments).integrated(), -23.0, 0.1);
+        }
+    }
+
+    // EBU Tech 3342, cas 1 a 3
+    #[test]
+    fn loudness_range_of_two_levels() {
+        for (first, second, expected) in [
+            (-20.0, -30.0, 10.0),
+            (-20.0, -15.0, 5.0),
+            (-40.0, -20.0, 20.0),
+        ] {
+            let (low, high) = measure(&[(first, 20.0), (second, 20.0)])
+                .range()
+                .expect("no range");
+            assert!(
+                (high - low - expected).abs() <= 1.0,
+                "expected {} LU, got {}",
+                expected,
+                high - low
+            );
+        }
+    }
+
+    #[test]
+    fn silence_has_no_loudness() {
+        let loudness = measure(&[(-200.0, 5.0)]);
+        assert_eq!(loudness.integrated(), None);
+        assert_eq!(loudness.range(), None);
+    }
+
+    // A fs/4 dephase de 45 degres, les echantillons restent a -3 dB du vrai pic
+    #[test]
+    fn true_peak_finds_inter_sample_peaks() {
+        let signal: Vec<f32> = (0..4800)
+            .map(|n| (PI / 2.0 * n as f64 + PI / 4.0).sin() as f32 * 0.5)
+            .collect();
+        let sample_peak = signal.iter().fold(0.0f32, |p, &x| p.max(x.abs()));
+        assert!((20.0 * (sample_peak as f64).log10() + 9.03).abs() < 0.01);
+
+        let mut true_peak = TruePeak::new(1);
+        true_peak.push(&signal);
+        assert_near(true_peak.dbtp(), -6.02, 0.2);
+    }
+}
@@ -1,164 +1,668 @@
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+mod analysis;
+mod capture;
+mod chroma;
+mod config;
+mod device;
+mod diagnostics;
+mod generator;
+mod legacy;
+mod loopback;
+mod loudness;
+mod meters;
+mod offline;
+mod pipeline;
+mod scope;
+mod shapes;
+mod source;
+mod spectrum;
+mod trigger;
+mod tuner;
+mod vad;
+
+use analysis::{Analyzer, FRAME_SIZE, HOP_SIZE};
+use capture::CaptureSession;
+use chroma::Chromagram;
+use config::CONFIG_FILE;
+use cpal::traits::{DeviceTrait, StreamTrait};
+use device::InputOptions;
+use diagnostics::{Diagnostics, DiagnosticsReading};
+use generator::{Generator, Signal};
+use legacy::{Legacy, Meter};
+use loudness::{Loudness, LoudnessReading, TruePeak};
+use meters::{ChannelLevel, ChannelMeters};
+use pipeline::AtomicF32;
 use piston_window::*;
-use rand::Rng;
-use rustfft::FftPlanner;
+use scope::ScopeSender;
+use shapes::ShapeVisual;
+use source::AudioSource;
+use spectrum::{Bars, Waterfall};
 use std::env;
+use std::fs::File;
+use std::io::BufWriter;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
+use trigger::TriggerRecorder;
+use tuner::Tuner;
+use vad::VoiceActivity;
+
+// En dessous, le pitch precedent est conserve plutot que de sauter sur du bruit
+const MIN_CONFIDENCE: f32 = 0.3;
+
+// Valeurs publiees par le thread d'analyse pour la boucle de rendu
+#[derive(Default)]
+struct Readings {
+    decibels: AtomicF32,
+    pitch: AtomicF32,
+    confidence: AtomicF32,
+    spectrum: Mutex<Vec<f32>>,
+    // Niveaux par canal, et canal suivi par le pitch (0 pour le mixage)
+    levels: Mutex<Vec<ChannelLevel>>,
+    pitch_channel: AtomicUsize,
+    loudness: Mutex<LoudnessReading>,
+    // Demande de remise a zero de l'integree, de la LRA et du true peak
+    reset_loudness: AtomicBool,
+    diagnostics: Mutex<DiagnosticsReading>,
+    // Demande de remise a zero des compteurs d'ecretage et de decrochages
+    reset_diagnostics: AtomicBool,
+    // Arret de la capture : fin d'enregistrement ou fermeture de la fenetre
+    stop: AtomicBool,
+    // Un clip declenche est en cours d'enregistrement
+    triggered: AtomicBool,
+    // Le detecteur d'activite vocale est dans un segment de parole
+    speech: AtomicBool,
+}
+
+fn capture_audio(
+    source: AudioSource,
+    readings: Arc<Readings>,
+    mut scope: ScopeSender,
+    mut capture: Option<CaptureSession>,
+    mut trigger: Option<TriggerRecorder>,
+    mut vad: Option<VoiceActivity>,
+    mut diagnostics: Diagnostics,
+) {
+    let sample_rate = source.sample_rate();
+    let channels = source.channels() as usize;
+
+    // Le callback ne fait que copier les echantillons, l'analyse tourne sur ce thread
+    let (mut sender, mut reader, stats) = pipeline::sample_channel(
+        sample_rate as usize * channels,
+        HOP_SIZE * channels,
+        channels,
+        sample_rate,
+    );
+
+    let running = source
+        .start(move |data, info| {
+            sender.push(data, info);
+            scope.push(data);
+        })
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        });
+
+    let mut analyzer = Analyzer::new(FRAME_SIZE, sample_rate as f32);
+    let mut mono = Vec::with_capacity(HOP_SIZE);
+    let mut loudness = Loudness::new(channels, sample_rate);
+    let mut true_peak = TruePeak::new(channels);
+    let mut meters = ChannelMeters::new(channels, sample_rate);
+
+    // Analyse l'audio en continu jusqu'a l'arret
+    while !readings.stop.load(Ordering::Relaxed) {
+        while let Some(hop) = reader.next_frame() {
+            // Le pitch suit le canal choisi (touche C), ou la moyenne des canaux
+            let selected = readings.pitch_channel.load(Ordering::Relaxed);
+            meters::select(hop, channels, selected, &mut mono);
+            if readings.reset_diagnostics.swap(false, Ordering::Relaxed) {
+                diagnostics.reset();
+            }
+            diagnostics.push(hop);
+            let analysis = analyzer.push(&mono);
+            readings.decibels.store(analysis.decibels);
+            readings.confidence.store(analysis.confidence);
+            if analysis.confidence >= MIN_CONFIDENCE {
+                readings.pitch.store(analysis.pitch);
+            }
+            let mut spectrum = readings.spectrum.lock().unwrap();
+            spectrum.clear();
+            spectrum.extend_from_slice(analyzer.magnitudes());
+            meters.push(hop);
+            let mut levels = readings.levels.lock().unwrap();
+            levels.clear();
+            levels.extend_from_slice(meters.levels());
 
-fn capture_audio(decibels: Arc<Mutex<f32>>, pitch: Arc<Mutex<f32>>, duration: u64) {
-    let host = cpal::default_host();
-    let device = host
-        .default_input_device()
-        .expect("No input device available");
-    let config = device
-        .default_input_config()
-        .expect("Failed to get default input format");
-
-    let stream_config = config.clone().into();
-
-    let stream = device
-        .build_input_stream(
-            &stream_config,
-            move |data: &[f32], _: &cpal::InputCallbackInfo| {
-                let rms = (data.iter().map(|&x| x * x).sum::<f32>() / data.len() as f32).sqrt();
-                let mut decibels = decibels.lock().unwrap();
-                *decibels = 20.0 * rms.log10();
-
-                // Analyse du pitch
-                let mut planner = FftPlanner::new();
-                let fft = planner.plan_fft_forward(data.len());
-                let mut buffer: Vec<_> = data
-                    .iter()
-                    .map(|&x| rustfft::num_complex::Complex::new(x, 0.0))
-                    .collect();
-                fft.process(&mut buffer);
-
-                let mut max_magnitude = 0.0;
-                let mut max_index = 0;
-                for (i, &complex) in buffer.iter().enumerate() {
-                    let magnitude = complex.norm();
-                    if magnitude > max_magnitude {
-                        max_magnitude = magnitude;
-                        max_index = i;
-                    }
+            if readings.reset_loudness.swap(false, Ordering::Relaxed) {
+                loudness.reset();
+                true_peak.reset();
+            }
+            loudness.push(hop);
+            true_peak.push(hop);
+            *readings.loudness.lock().unwrap() = LoudnessReading {
+                momentary: loudness.momentary(),
+                short_term: loudness.short_term(),
+                integrated: loudness.integrated(),
+                range: loudness.range(),
+                true_peak: true_peak.dbtp(),
+            };
+
+            if let Some(recorder) = &mut trigger {
+                if let Err(err) = recorder.push(hop, &analysis) {
+                    eprintln!("{}", err);
+                    readings.stop.store(true, Ordering::Relaxed);
+                    break;
                 }
+                readings
+                    .triggered
+                    .store(recorder.is_recording(), Ordering::Relaxed);
+            }
 
-                let sample_rate = stream_config.sample_rate.0 as f32;
-                let frequency = max_index as f32 * sample_rate / data.len() as f32;
-
-                let mut pitch = pitch.lock().unwrap();
-                *pitch = frequency;
-            },
-            move |err| {
-                eprintln!("Error: {:?}", err);
-            },
-            None,
-        )
-        .unwrap();
+            if let Some(detector) = &mut vad {
+                if let Err(err) = detector.push(hop, &analysis, analyzer.magnitudes()) {
+                    eprintln!("{}", err);
+                    readings.stop.store(true, Ordering::Relaxed);
+                    break;
+                }
+                readings
+                    .speech
+                    .store(detector.is_speech(), Ordering::Relaxed);
+            }
 
-    stream.play().unwrap();
+            if let Some(session) = &mut capture {
+                let done = session.push(hop, &analysis).unwrap_or_else(|err| {
+                    eprintln!("{}", err);
+                    true
+                });
+                if done {
+                    readings.stop.store(true, Ordering::Relaxed);
+                    break;
+                }
+            }
+        }
+        diagnostics.check_pipeline(&stats);
+        readings
+            .diagnostics
+            .lock()
+            .unwrap()
+            .clone_from(diagnostics.reading());
+        std::thread::sleep(std::time::Duration::from_millis(5));
+    }
 
-    // Boucle infinie pour capturer l'audio en continu
-    loop {
-        std::thread::sleep(std::time::Duration::from_secs(duration));
+    drop(running);
+    diagnostics.finish();
+    if let Some(mut recorder) = trigger {
+        if let Err(err) = recorder.finish() {
+            eprintln!("{}", err);
+        }
+    }
+    if let Some(mut detector) = vad {
+        if let Err(err) = detector.finish() {
+            eprintln!("{}", err);
+        }
+    }
+    if let Some(session) = capture {
+        if let Err(err) = session.finish() {
+            eprintln!("{}", err);
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Shapes,
+    Spectrum,
+    Waterfall,
+    Tuner,
+    Scope,
+    Lissajous,
+    Loudness,
+    Chords,
+    Meter,
+    Legacy,
+    Diagnostics,
+    Channels,
+}
+
+impl Mode {
+    fn from_args(args: &[String]) -> Result<Self, String> {
+        let mode = args
+            .iter()
+            .find(|arg| arg.starts_with("mode="))
+            .and_then(|arg| arg.split('=').nth(1))
+            .unwrap_or("shapes");
+        match mode {
+            "shapes" => Ok(Mode::Shapes),
+            "spectrum" => Ok(Mode::Spectrum),
+            "waterfall" => Ok(Mode::Waterfall),
+            "tuner" => Ok(Mode::Tuner),
+            "scope" => Ok(Mode::Scope),
+            "lissajous" => Ok(Mode::Lissajous),
+            "loudness" => Ok(Mode::Loudness),
+            "chords" => Ok(Mode::Chords),
+            "meter" => Ok(Mode::Meter),
+            "legacy" => Ok(Mode::Legacy),
+            "diagnostics" => Ok(Mode::Diagnostics),
+            "channels" => Ok(Mode::Channels),
+            other => Err(format!("Unknown mode '{}'", other)),
+        }
+    }
+
+    // Touche M : passe au mode suivant
+    fn next(self) -> Self {
+        match self {
+            Mode::Shapes => Mode::Spectrum,
+            Mode::Spectrum => Mode::Waterfall,
+            Mode::Waterfall => Mode::Tuner,
+            Mode::Tuner => Mode::Scope,
+            Mode::Scope => Mode::Lissajous,
+            Mode::Lissajous => Mode::Loudness,
+            Mode::Loudness => Mode::Chords,
+            Mode::Chords => Mode::Meter,
+            Mode::Meter => Mode::Legacy,
+            Mode::Legacy => Mode::Diagnostics,
+            Mode::Diagnostics => Mode::Channels,
+            Mode::Channels => Mode::Shapes,
+        }
     }
 }
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    // Les options de la ligne de commande passent avant celles du fichier de config
+    let mut args: Vec<String> = env::args().collect();
+    args.extend(config::load_config(CONFIG_FILE));
 
     const DEFAULT_DURATION: u64 = 30;
 
+    if args.contains(&String::from("list-devices")) {
+        device::list_devices();
+        return;
+    }
+
+    // Analyse hors ligne d'un fichier WAV, sans carte son ni fenetre
+    if let Some(path) = args
+        .iter()
+        .find(|arg| arg.starts_with("analyze="))
+        .and_then(|arg| arg.split('=').nth(1))
+    {
+        let output = args
+            .iter()
+            .find(|arg| arg.starts_with("out="))
+            .and_then(|arg| arg.split('=').nth(1));
+        let result = match output {
+            Some(output) => File::create(output)
+                .map_err(|err| format!("Failed to create {}: {}", output, err))
+                .and_then(|file| offline::analyze_file(path, &mut BufWriter::new(file))),
+            None => offline::analyze_file(path, &mut std::io::stdout().lock()),
+        };
+        match result {
+            Ok(hops) => eprintln!("{} hops of {} samples analyzed", hops, HOP_SIZE),
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let level = args
+        .iter()
+        .find(|arg| arg.starts_with("level="))
+        .and_then(|arg| arg.split('=').nth(1))
+        .map(|arg| arg.parse::<f32>().expect("Invalid level"))
+        .unwrap_or(generator::DEFAULT_LEVEL);
+    let output_name = args
+        .iter()
+        .find(|arg| arg.starts_with("output="))
+        .and_then(|arg| arg.split('=').nth(1));
+
+    // Mesure de latence et de reponse en frequence, sortie reliee a l'entree
+    if args.contains(&String::from("loopback")) {
+        let out = args
+            .iter()
+            .find(|arg| arg.starts_with("out="))
+            .and_then(|arg| arg.split('=').nth(1));
+        let result = device::open_input(&InputOptions::from_args(&args))
+            .and_then(|input| {
+                let output = device::open_output(output_name, input.1.sample_rate().0)?;
+                Ok((input, output))
+            })
+            .and_then(|(input, output)| match out {
+                Some(out) => {
+                    let file = File::create(out)
+                        .map_err(|err| format!("Failed to create {}: {}", out, err))?;
+                    loopback::run(input, output, level, Some(&mut BufWriter::new(file)))
+                }
+                None => loopback::run(input, output, level, None),
+            });
+        if let Err(err) = result {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // La duree reste le premier argument, les options sont de la forme cle=valeur
     let duration = args
         .get(1)
-        .unwrap_or(&DEFAULT_DURATION.to_string())
-        .parse::<u64>()
-        .expect("Invalid duration");
+        .filter(|arg| !arg.contains('=') && *arg != "help")
+        .map(|arg| arg.parse::<u64>().expect("Invalid duration"))
+        .unwrap_or(DEFAULT_DURATION);
 
-    let decibels = Arc::new(Mutex::new(0.0));
-    let pitch = Arc::new(Mutex::new(0.0));
-    let decibels_clone = Arc::clone(&decibels);
-    let pitch_clone = Arc::clone(&pitch);
+    let (mut mode, tuner) = Mode::from_args(&args)
+        .and_then(|mode| Ok((mode, Tuner::from_args(&args)?)))
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        });
+
+    let source = AudioSource::from_args(&args, level).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    });
+    println!("Input: {}", source.describe());
+    let channels = source.channels() as usize;
+    let sample_rate = source.sample_rate();
+    let pitch_channel = meters::pitch_channel(&args, channels).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    });
+
+    // Signal de test joue sur la sortie pendant la visualisation : generate=<signal>
+    let _generator_stream = args
+        .iter()
+        .find(|arg| arg.starts_with("generate="))
+        .and_then(|arg| arg.split('=').nth(1))
+        .map(|spec| {
+            Signal::parse(spec)
+                .and_then(|signal| {
+                    let (device, config) = device::open_output(output_name, sample_rate)?;
+                    let output_channels = config.channels() as usize;
+                    let mut generator = Generator::new(signal, sample_rate, level);
+                    println!(
+                        "Output: {} playing {} at {} dBFS",
+                        device.name().unwrap_or_default(),
+                        spec,
+                        level
+                    );
+                    let stream = device::build_output_stream(
+                        &device,
+                        &config,
+                        move |data| generator.fill(data, output_channels),
+                        |err| eprintln!("Error: {:?}", err),
+                    )?;
+                    stream
+                        .play()
+                        .map_err(|err| format!("Failed to start output stream: {}", err))?;
+                    Ok(stream)
+                })
+                .unwrap_or_else(|err| {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                })
+        });
 
-    std::thread::spawn(move || {
-        capture_audio(decibels_clone, pitch_clone, duration);
+    let timebase = args
+        .iter()
+        .find(|arg| arg.starts_with("timebase="))
+        .and_then(|arg| arg.split('=').nth(1))
+        .map(|arg| arg.parse::<f64>().expect("Invalid timebase"))
+        .unwrap_or(scope::DEFAULT_TIMEBASE_MS);
+    let (scope_sender, mut scope) = scope::scope_channel(channels, sample_rate, timebase);
+
+    // Session d'enregistrement : capture=<fichier.wav> [seconds=<N>]
+    let capture = args
+        .iter()
+        .find(|arg| arg.starts_with("capture="))
+        .and_then(|arg| arg.split('=').nth(1))
+        .map(|path| {
+            let seconds = args
+                .iter()
+                .find(|arg| arg.starts_with("seconds="))
+                .and_then(|arg| arg.split('=').nth(1))
+                .map(|arg| arg.parse::<f64>().expect("Invalid seconds"));
+            CaptureSession::create(path, channels, sample_rate, seconds, tuner.clone())
+                .unwrap_or_else(|err| {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                })
+        });
+    let capturing = capture.is_some();
+    if capturing {
+        println!("Recording, press Space to stop");
+    }
+
+    // Enregistrement declenche par le niveau ou la voix : trigger=<prefixe>
+    let trigger = args.iter().any(|arg| arg.starts_with("trigger=")).then(|| {
+        TriggerRecorder::from_args(&args, channels, sample_rate).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        })
     });
 
+    // Detection d'activite vocale : vad, vad=<segments.csv>, vad_clips=<prefixe>
+    let vad = VoiceActivity::from_args(&args, FRAME_SIZE, HOP_SIZE, channels, sample_rate)
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        });
+    let mut vad_overlay = vad.is_some().then(vad::Overlay::new);
+
+    // Ecretage, composante continue et decrochages, journal optionnel : diag_log=<fichier>
+    let diagnostics = Diagnostics::from_args(&args, channels, sample_rate).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    });
+
+    let sample_rate = sample_rate as f32;
+    let readings = Arc::new(Readings::default());
+    readings
+        .pitch_channel
+        .store(pitch_channel, Ordering::Relaxed);
+    let readings_clone = Arc::clone(&readings);
+
+    let audio_thread = std::thread::spawn(move || {
+        capture_audio(
+            source,
+            readings_clone,
+            scope_sender,
+            capture,
+            trigger,
+            vad,
+            diagnostics,
+        );
+    });
+    let start = std::time::Instant::now();
+
     let mut window: PistonWindow = WindowSettings::new("Audio Visualizer", [800, 600])
         .exit_on_esc(true)
         .build()
         .unwrap();
 
-    let colors = [
-        [1.0, 0.0, 0.0, 1.0], // Red
-        [0.0, 1.0, 0.0, 1.0], // Green
-        [0.0, 0.0, 1.0, 1.0], // Blue
-        [1.0, 1.0, 0.0, 1.0], // Yellow
-        [1.0, 0.0, 1.0, 1.0], // Magenta
-        [0.0, 1.0, 1.0, 1.0], // Cyan
-        [0.5, 0.5, 0.5, 1.0], // Gray
-        [1.0, 0.5, 0.0, 1.0], // Orange
-    ];
-
-    let mut color_index = 0;
-    let mut frame_count = 0;
+    let mut shapes = ShapeVisual::from_args(&args).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    });
+    let mut last_render = std::time::Instant::now();
+    let mut title = String::new();
+    let mut bars = Bars::new(FRAME_SIZE, sample_rate);
+    let mut waterfall = Waterfall::new(FRAME_SIZE, sample_rate);
+    let mut chromagram = Chromagram::new(FRAME_SIZE, sample_rate, tuner.a4());
+    let mut meter = Meter::new();
+    let mut legacy = Legacy::new();
+    let mut magnitudes = Vec::new();
 
     while let Some(event) = window.next() {
-        let decibels = *decibels.lock().unwrap();
-        let pitch = *pitch.lock().unwrap();
-        let radius = ((decibels + 60.0).max(0.0) * 20.0) as f64; // Ajuster l'échelle pour rendre la taille plus sensible aux décibels
-
-        if frame_count % 10 == 0 {
-            color_index = rand::thread_rng().gen_range(0..colors.len());
+        if readings.stop.load(Ordering::Relaxed) {
+            break;
         }
 
-        let color = colors[color_index];
+        match event.press_args() {
+            Some(Button::Keyboard(Key::Space)) if capturing => {
+                readings.stop.store(true, Ordering::Relaxed)
+            }
+            Some(Button::Keyboard(Key::M)) => mode = mode.next(),
+            Some(Button::Keyboard(Key::Up)) => scope.zoom(2.0),
+            Some(Button::Keyboard(Key::Down)) => scope.zoom(0.5),
+            Some(Button::Keyboard(Key::C)) => {
+                let selected = readings.pitch_channel.load(Ordering::Relaxed);
+                readings
+                    .pitch_channel
+                    .store(meters::next_channel(selected, channels), Ordering::Relaxed);
+            }
+            Some(Button::Keyboard(Key::R)) => {
+                readings.reset_loudness.store(true, Ordering::Relaxed);
+                readings.reset_diagnostics.store(true, Ordering::Relaxed);
+            }
+            _ => {}
+        }
 
-        // ...existing code...
+        let decibels = readings.decibels.load();
+        let pitch = readings.pitch.load();
+        let loudness = *readings.loudness.lock().unwrap();
         let Size { width, height } = window.draw_size();
-        window.draw_2d(&event, |c, g, _| {
-            clear([0.0; 4], g);
-
-            let center_x = (width as f64 / 2.0).floor();
-            let center_y = (height as f64 / 2.0).floor();
-
-            // Change the shape based on the pitch
-            if pitch < 200.0 {
-                ellipse(
-                    color,
-                    [
-                        center_x - radius, // Left edge
-                        center_y - radius, // Top edge
-                        radius * 2.0,      // Width
-                        radius * 2.0,      // Height
-                    ],
-                    c.transform,
-                    g,
-                );
-            } else if pitch < 400.0 {
-                rectangle(
-                    color,
-                    [
-                        center_x - radius, // Left edge
-                        center_y - radius, // Top edge
-                        radius * 2.0,      // Width
-                        radius * 2.0,      // Height
-                    ],
-                    c.transform,
-                    g,
-                );
-            } else {
-                let points = [
-                    [center_x, center_y - radius],          // Top vertex
-                    [center_x - radius, center_y + radius], // Bottom left vertex
-                    [center_x + radius, center_y + radius], // Bottom right vertex
-                ];
-                let points_f64: Vec<[f64; 2]> = points.iter().map(|&p| [p[0], p[1]]).collect();
-                polygon(color, &points_f64, c.transform, g);
+
+        // Une ligne de spectrogramme par image, meme hors du mode affiche
+        if event.render_args().is_some() {
+            let now = std::time::Instant::now();
+            shapes.update(decibels, pitch, (now - last_render).as_secs_f64());
+            last_render = now;
+            magnitudes.clone_from(&readings.spectrum.lock().unwrap());
+            bars.update(&magnitudes);
+            waterfall.update(&magnitudes);
+            chromagram.update(&magnitudes);
+            scope.update();
+            if let Some(overlay) = &mut vad_overlay {
+                overlay.update(readings.speech.load(Ordering::Relaxed));
             }
-        });
+        }
+
+        let note = if readings.confidence.load() >= MIN_CONFIDENCE {
+            tuner.nearest(pitch)
+        } else {
+            None
+        };
+        let text = match mode {
+            Mode::Shapes => String::from("Audio Visualizer"),
+            Mode::Spectrum => String::from("Audio Visualizer - spectrum"),
+            Mode::Waterfall => String::from("Audio Visualizer - waterfall"),
+            Mode::Tuner => tuner::title(note.as_ref(), pitch, tuner.a4()),
+            Mode::Scope => format!("Audio Visualizer - oscilloscope {} ms", scope.timebase_ms()),
+            Mode::Lissajous => String::from("Audio Visualizer - lissajous"),
+            Mode::Loudness => loudness::title(&loudness),
+            Mode::Chords => chroma::title(chromagram.chord()),
+            Mode::Meter | Mode::Legacy => String::from("Audio Visualizer"),
+            Mode::Diagnostics => diagnostics::title(&readings.diagnostics.lock().unwrap()),
+            Mode::Channels => meters::title(
+                &readings.levels.lock().unwrap(),
+                readings.pitch_channel.load(Ordering::Relaxed),
+            ),
+        };
+        let text = if capturing {
+            format!("REC {} s - {}", start.elapsed().as_secs(), text)
+        } else {
+            text
+        };
+        let text = if readings.triggered.load(Ordering::Relaxed) {
+            format!("TRIGGERED - {}", text)
+        } else {
+            text
+        };
+        let text = if readings.speech.load(Ordering::Relaxed) {
+            format!("SPEECH - {}", text)
+        } else {
+            text
+        };
+        if text != title {
+            window.set_title(text.clone());
+            title = text;
+        }
 
-        frame_count += 1;
-        std::thread::sleep(std::time::Duration::from_millis(duration));
+        match mode {
+            Mode::Spectrum => {
+                window.draw_2d(&event, |c, g, _| {
+                    clear([0.0; 4], g);
+                    bars.draw(width, height, c, g);
+                });
+            }
+            Mode::Waterfall => {
+                window.draw_2d(&event, |c, g, _| {
+                    clear([0.0; 4], g);
+                    waterfall.draw(width, height, c, g);
+                });
+            }
+            Mode::Tuner => {
+                window.draw_2d(&event, |c, g, _| {
+                    clear([0.0; 4], g);
+                    tuner::draw(note.as_ref(), width, height, c, g);
+                });
+            }
+            Mode::Scope => {
+                window.draw_2d(&event, |c, g, _| {
+                    clear([0.0; 4], g);
+                    scope.draw_oscilloscope(width, height, c, g);
+                });
+            }
+            Mode::Lissajous => {
+                window.draw_2d(&event, |c, g, _| {
+                    clear([0.0; 4], g);
+                    scope.draw_lissajous(width, height, c, g);
+                });
+            }
+            Mode::Loudness => {
+                window.draw_2d(&event, |c, g, _| {
+                    clear([0.0; 4], g);
+                    loudness::draw(&loudness, width, height, c, g);
+                });
+            }
+            Mode::Chords => {
+                window.draw_2d(&event, |c, g, _| {
+                    clear([0.0; 4], g);
+                    chromagram.draw(width, height, c, g);
+                });
+            }
+            Mode::Meter => {
+                window.draw_2d(&event, |c, g, _| {
+                    meter.draw(decibels, width, height, c, g);
+                });
+            }
+            Mode::Legacy => {
+                window.draw_2d(&event, |c, g, _| {
+                    legacy.draw(decibels, pitch, width, height, c, g);
+                });
+            }
+            Mode::Diagnostics => {
+                let reading = readings.diagnostics.lock().unwrap().clone();
+                window.draw_2d(&event, |c, g, _| {
+                    clear([0.0; 4], g);
+                    diagnostics::draw(&reading, width, height, c, g);
+                });
+            }
+            Mode::Channels => {
+                let levels = readings.levels.lock().unwrap().clone();
+                let selected = readings.pitch_channel.load(Ordering::Relaxed);
+                window.draw_2d(&event, |c, g, _| {
+                    clear([0.0; 4], g);
+                    meters::draw(&levels, selected, width, height, c, g);
+                });
+            }
+            Mode::Shapes => {
+                window.draw_2d(&event, |c, g, _| {
+                    clear([0.0; 4], g);
+                    shapes.draw(width, height, c, g);
+                });
+            }
+        }
+        if let Some(overlay) = &vad_overlay {
+            window.draw_2d(&event, |c, g, _| overlay.draw(width, height, c, g));
+        }
+
+        // Les modes historiques dessinent sans effacer : le delai regle leur vitesse
+        if matches!(mode, Mode::Meter | Mode::Legacy) && event.render_args().is_some() {
+            std::thread::sleep(std::time::Duration::from_millis(duration));
+        }
     }
+
+    // Laisse le thread audio finir le fichier et afficher le rapport
+    readings.stop.store(true, Ordering::Relaxed);
+    audio_thread.join().ok();
 }
@@ -0,0 +1,263 @@
+use crate::analysis::MIN_DECIBELS;
+use piston_window::*;
+
+// Echelle des vu-metres
+const METER_MIN: f32 = -60.0;
+const METER_MAX: f32 = 0.0;
+// La crete maintenue reste 1.5 s puis redescend de 20 dB par seconde
+const HOLD_SECONDS: f32 = 1.5;
+const FALL_RATE: f32 = 20.0;
+// Couleurs au-dessus de -18 et de -6 dBFS
+const WARNING_LEVEL: f32 = -18.0;
+const DANGER_LEVEL: f32 = -6.0;
+
+#[derive(Clone, Copy)]
+pub struct ChannelLevel {
+    pub rms: f32,
+    pub peak: f32,
+    pub hold: f32,
+}
+
+impl Default for ChannelLevel {
+    fn default() -> Self {
+        ChannelLevel {
+            rms: MIN_DECIBELS,
+            peak: MIN_DECIBELS,
+            hold: MIN_DECIBELS,
+        }
+    }
+}
+
+// RMS and peak of every input channel over the last hop, with peak hold.
+pub struct ChannelMeters {
+    channels: usize,
+    sample_rate: f32,
+    levels: Vec<ChannelLevel>,
+    // Temps depuis la derniere crete maintenue, par canal
+    held: Vec<f32>,
+}
+
+impl ChannelMeters {
+    pub fn new(channels: usize, sample_rate: u32) -> Self {
+        let channels = channels.max(1);
+        ChannelMeters {
+            channels,
+            sample_rate: sample_rate as f32,
+            levels: vec![ChannelLevel::default(); channels],
+            held: vec![0.0; channels],
+        }
+    }
+
+    // `hop` holds interleaved samples.
+    pub fn push(&mut self, hop: &[f32]) {
+        let frames = hop.len() / self.channels;
+        if frames == 0 {
+            return;
+        }
+        let seconds = frames as f32 / self.sample_rate;
+        let decibels = |value: f32| (20.0 * value.log10()).max(MIN_DECIBELS);
+
+        for (channel, level) in self.levels.iter_mut().enumerate() {
+            let (mut power, mut peak) = (0.0, 0.0f32);
+            for &sample in hop.iter().skip(channel).step_by(self.channels) {
+                power += sample * sample;
+                peak = peak.max(sample.abs());
+            }
+            level.rms = decibels((power / frames as f32).sqrt());
+            level.peak = decibels(peak);
+
+            let held = &mut self.held[channel];
+            if level.peak >= level.hold {
+                level.hold = level.peak;
+                *held = 0.0;
+            } else {
+                *held += seconds;
+                if *held > HOLD_SECONDS {
+                    level.hold = (level.hold - FALL_RATE * seconds).max(level.peak);
+                }
+            }
+        }
+    }
+
+    pub fn levels(&self) -> &[ChannelLevel] {
+        &self.levels
+    }
+}
+
+// Canal suivi par l'analyse de pitch : pitch_channel=<n> (1 a N), 0 ou absent pour le mixage
+pub fn pitch_channel(args: &[String], channels: usize) -> Result<usize, String> {
+    match args
+        .iter()
+        .find(|arg| arg.starts_with("pitch_channel="))
+        .and_then(|arg| arg.split('=').nth(1))
+    {
+        Some(value) => value
+            .parse::<usize>()
+            .ok()
+            .filter(|&channel| channel <= channels)
+            .ok_or_else(|| {
+                format!(
+                    "Invalid pitch_channel '{}', expected 0 (mix) to {}",
+                    value, channels
+                )
+            }),
+        None => Ok(0),
+    }
+}
+
+// Touche C : mixage, canal 1, canal 2, ... puis retour au mixage
+pub fn next_channel(selected: usize, channels: usize) -> usize {
+    if selected >= channels {
+        0
+    } else {
+        selected + 1
+    }
+}
+
+// Signal handed to the analyzer: the average of all channels when
+// `selected` is 0, channel `selected` (from 1) otherwise.
+pub fn select(hop: &[f32], channels: usize, selected: usize, mono: &mut Vec<f32>) {
+    mono.clear();
+    match selected {
+        0 => mono.extend(
+            hop.chunks(channels)
+                .map(|frame| frame.iter().sum::<f32>() / channels as f32),
+        ),
+        channel => mono.extend(hop.iter().skip(channel - 1).step_by(channels)),
+    }
+}
+
+// Un vu-metre vertical par canal : crete en clair, RMS par-dessus,
+// trait blanc pour la crete maintenue. Le canal analyse est souligne.
+pub fn draw(
+    levels: &[ChannelLevel],
+    selected: usize,
+    width: f64,
+    height: f64,
+    c: Context,
+    g: &mut G2d,
+) {
+    let top = height * 0.05;
+    let bottom = height * 0.9;
+    let y = |level: f32| {
+        let t = ((level - METER_MIN) / (METER_MAX - METER_MIN)).clamp(0.0, 1.0) as f64;
+        bottom - t * (bottom - top)
+    };
+
+    // Graduations tous les 6 dB
+    for level in (METER_MIN as i32..=METER_MAX as i32).step_by(6) {
+        let y = y(level as f32);
+        line(
+            [0.2, 0.2, 0.2, 1.0],
+            0.5,
+            [0.0, y, width, y],
+            c.transform,
+            g,
+        );
+    }
+
+    let column = width / levels.len().max(1) as f64;
+    let w = column * 0.6;
+    for (index, level) in levels.iter().enumerate() {
+        let x = column * index as f64 + (column - w) / 2.0;
+        let color = if level.peak > DANGER_LEVEL {
+            [1.0, 0.0, 0.0, 1.0]
+        } else if level.peak > WARNING_LEVEL {
+            [1.0, 0.8, 0.0, 1.0]
+        } else {
+            [0.0, 0.8, 0.2, 1.0]
+        };
+        rectangle(
+            [0.15, 0.15, 0.15, 1.0],
+            [x, top, w, bottom - top],
+            c.transform,
+            g,
+        );
+        let peak = y(level.peak);
+        let mut faded = color;
+        faded[3] = 0.35;
+        rectangle(faded, [x, peak, w, bottom - peak], c.transform, g);
+        let rms = y(level.rms);
+        rectangle(color, [x, rms, w, bottom - rms], c.transform, g);
+        if level.hold > METER_MIN {
+            let hold = y(level.hold);
+            line(
+                [1.0, 1.0, 1.0, 1.0],
+                1.0,
+                [x, hold, x + w, hold],
+                c.transform,
+                g,
+            );
+        }
+
+        if selected == 0 || selected == index + 1 {
+            rectangle(
+                [0.2, 0.6, 1.0, 1.0],
+                [x, height * 0.93, w, height * 0.02],
+                c.transform,
+                g,
+            );
+        }
+    }
+}
+
+pub fn title(levels: &[ChannelLevel], selected: usize) -> String {
+    let pitch = match selected {
+        0 => String::from("pitch on mix"),
+        channel => format!("pitch on {}", channel),
+    };
+    let levels: Vec<String> = levels
+        .iter()
+        .enumerate()
+        .map(|(index, level)| format!("{}: {:.0}/{:.0}", index + 1, level.rms, level.hold))
+        .collect();
+    format!("Channels - {} - {}", levels.join(" "), pitch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn meters_each_channel_with_peak_hold() {
+        let mut meters = ChannelMeters::new(2, 48000);
+        // Canal 1 : carre a -6 dBFS, canal 2 : silence
+        let mut hop = vec![0.0; 1024];
+        for (frame, pair) in hop.chunks_mut(2).enumerate() {
+            pair[0] = 0.5 * (-1f32).powi(frame as i32);
+        }
+        meters.push(&hop);
+        let levels = meters.levels();
+        assert!((levels[0].rms + 6.02).abs() < 0.01, "{}", levels[0].rms);
+        assert!((levels[0].peak + 6.02).abs() < 0.01, "{}", levels[0].peak);
+        assert_eq!(levels[1].rms, MIN_DECIBELS);
+
+        // La crete tient 1.5 s apres le retour au silence, puis redescend
+        hop.fill(0.0);
+        for _ in 0..(48000 / 512) {
+            meters.push(&hop);
+        }
+        assert!((meters.levels()[0].hold + 6.02).abs() < 0.01);
+        for _ in 0..(48000 / 512) {
+            meters.push(&hop);
+        }
+        let hold = meters.levels()[0].hold;
+        assert!(hold < -10.0 && hold > -30.0, "{}", hold);
+    }
+
+    #[test]
+    fn selects_the_pitch_channel() {
+        let hop = [0.25, 0.75, 0.5, 1.0];
+        let mut mono = Vec::new();
+        select(&hop, 2, 0, &mut mono);
+        assert_eq!(mono, [0.5, 0.75]);
+        select(&hop, 2, 2, &mut mono);
+        assert_eq!(mono, [0.75, 1.0]);
+
+        let args = |list: &[&str]| list.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert_eq!(pitch_channel(&args(&["pitch_channel=3"]), 4), Ok(3));
+        assert_eq!(pitch_channel(&args(&[]), 4), Ok(0));
+        assert!(pitch_channel(&args(&["pitch_channel=5"]), 4).is_err());
+        assert_eq!(next_channel(4, 4), 0);
+    }
+}
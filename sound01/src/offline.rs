@@ -0,0 +1,104 @@
+use crate::analysis::{Analyzer, FRAME_SIZE, HOP_SIZE};
+use hound::{SampleFormat, WavReader};
+use std::io::{Read, Write};
+
+// Runs the live analysis over a WAV file and writes one CSV line per hop:
+// time of the last sample of the frame in seconds, RMS and peak level in dB
+// over the frame, pitch in Hz and its confidence. The channels are averaged to
+// mono like on the live path, and a trailing partial hop is ignored.
+pub fn analyze_file<W: Write>(path: &str, out: &mut W) -> Result<usize, String> {
+    let reader =
+        WavReader::open(path).map_err(|err| format!("Failed to open {}: {}", path, err))?;
+    analyze(reader, out).map_err(|err| format!("{}: {}", path, err))
+}
+
+// Reads every sample as f32 in [-1, 1], still interleaved.
+pub fn read_samples<R: Read>(reader: WavReader<R>) -> Result<Vec<f32>, String> {
+    let spec = reader.spec();
+    let samples: Result<Vec<f32>, _> = match spec.sample_format {
+        SampleFormat::Float => reader.into_samples::<f32>().collect(),
+        SampleFormat::Int => {
+            // Entiers ramenes entre -1 et 1
+            let scale = 1.0 / (1u64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .into_samples::<i32>()
+                .map(|s| s.map(|s| s as f32 * scale))
+                .collect()
+        }
+    };
+    samples.map_err(|err| format!("invalid samples: {}", err))
+}
+
+fn analyze<R: Read, W: Write>(reader: WavReader<R>, out: &mut W) -> Result<usize, String> {
+    let spec = reader.spec();
+    let channels = spec.channels.max(1) as usize;
+    let samples = read_samples(reader)?;
+
+    let write_error = |err: std::io::Error| format!("write failed: {}", err);
+    writeln!(out, "time,rms_db,peak_db,pitch,confidence").map_err(write_error)?;
+
+    let mut analyzer = Analyzer::new(FRAME_SIZE, spec.sample_rate as f32);
+    let mut mono = Vec::with_capacity(HOP_SIZE);
+    let mut hops = 0;
+    for hop in samples.chunks_exact(HOP_SIZE * channels) {
+        mono.clear();
+        mono.extend(
+            hop.chunks(channels)
+                .map(|frame| frame.iter().sum::<f32>() / channels as f32),
+        );
+        let analysis = analyzer.push(&mono);
+        hops += 1;
+        let time = (hops * HOP_SIZE) as f64 / spec.sample_rate as f64;
+        writeln!(
+            out,
+            "{:.6},{:.2},{:.2},{:.2},{:.3}",
+            time, analysis.decibels, analysis.peak_decibels, analysis.pitch, analysis.confidence
+        )
+        .map_err(write_error)?;
+    }
+    Ok(hops)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hound::{WavSpec, WavWriter};
+    use std::f32::consts::PI;
+
+    #[test]
+    fn stereo_int_wav_gives_one_line_per_hop() {
+        let path = std::env::temp_dir().join("sound01_offline_test.wav");
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate: 44100,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let mut writer = WavWriter::create(&path, spec).unwrap();
+        // Une seconde de 440 Hz a -6 dB sur les deux canaux
+        for i in 0..44100 {
+            let sample = 0.5 * (2.0 * PI * 440.0 * i as f32 / 44100.0).sin();
+            let sample = (sample * i16::MAX as f32) as i16;
+            writer.write_sample(sample).unwrap();
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let mut csv = Vec::new();
+        let hops = analyze_file(path.to_str().unwrap(), &mut csv).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(hops, 44100 / HOP_SIZE);
+
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "time,rms_db,peak_db,pitch,confidence");
+        assert_eq!(lines.len(), hops + 1);
+
+        let values: Vec<f32> = lines[hops].split(',').map(|v| v.parse().unwrap()).collect();
+        assert!((values[0] - (hops * HOP_SIZE) as f32 / 44100.0).abs() < 1e-5);
+        assert!((values[1] + 9.03).abs() < 0.1, "rms {}", values[1]);
+        assert!((values[2] + 6.02).abs() < 0.1, "peak {}", values[2]);
+        assert!((values[3] - 440.0).abs() < 1.0, "pitch {}", values[3]);
+        assert!(values[4] > 0.9, "confidence {}", values[4]);
+    }
+}
@@ -0,0 +1,287 @@
+use piston_window::*;
+use rtrb::{Consumer, Producer, RingBuffer};
+
+pub const DEFAULT_TIMEBASE_MS: f64 = 20.0;
+const MIN_TIMEBASE_MS: f64 = 1.0;
+const MAX_TIMEBASE_MS: f64 = 200.0;
+// Historique conserve : de quoi chercher un declenchement avant la fenetre la plus longue
+const HISTORY_SECONDS: f64 = 2.0 * MAX_TIMEBASE_MS / 1000.0;
+// Points traces dans la vue X-Y
+const LISSAJOUS_POINTS: usize = 2048;
+
+// Callback side: keeps the first two channels of each frame, or duplicates a
+// mono input. Samples that do not fit are dropped, the scope only needs the latest.
+pub struct ScopeSender {
+    producer: Producer<[f32; 2]>,
+    channels: usize,
+}
+
+impl ScopeSender {
+    pub fn push(&mut self, data: &[f32]) {
+        let frames = data.len() / self.channels;
+        let written = frames.min(self.producer.slots());
+        if let Ok(chunk) = self.producer.write_chunk_uninit(written) {
+            chunk.fill_from_iter(data.chunks(self.channels).map(|frame| match frame {
+                [left, right, ..] => [*left, *right],
+                [mono] => [*mono, *mono],
+                _ => [0.0; 2],
+            }));
+        }
+    }
+}
+
+// Render side: the last HISTORY_SECONDS of stereo frames, in a circular buffer.
+pub struct Scope {
+    consumer: Consumer<[f32; 2]>,
+    history: Vec<[f32; 2]>,
+    position: usize,
+    sample_rate: f64,
+    timebase_ms: f64,
+}
+
+pub fn scope_channel(channels: usize, sample_rate: u32, timebase_ms: f64) -> (ScopeSender, Scope) {
+    let length = (sample_rate as f64 * HISTORY_SECONDS) as usize;
+    let (producer, consumer) = RingBuffer::new(length);
+    let sender = ScopeSender {
+        producer,
+        channels: channels.max(1),
+    };
+    let scope = Scope {
+        consumer,
+        history: vec![[0.0; 2]; length],
+        position: 0,
+        sample_rate: sample_rate as f64,
+        timebase_ms: timebase_ms.clamp(MIN_TIMEBASE_MS, MAX_TIMEBASE_MS),
+    };
+    (sender, scope)
+}
+
+impl Scope {
+    pub fn update(&mut self) {
+        let Ok(chunk) = self.consumer.read_chunk(self.consumer.slots()) else {
+            return;
+        };
+        for frame in chunk {
+            self.history[self.position] = frame;
+            self.position = (self.position + 1) % self.history.len();
+        }
+    }
+
+    pub fn timebase_ms(&self) -> f64 {
+        self.timebase_ms
+    }
+
+    // Fleches haut et bas : double ou divise par deux la duree affichee
+    pub fn zoom(&mut self, factor: f64) {
+        self.timebase_ms = (self.timebase_ms * factor).clamp(MIN_TIMEBASE_MS, MAX_TIMEBASE_MS);
+    }
+
+    // Frame `age` samples before the newest one.
+    fn at(&self, age: usize) -> [f32; 2] {
+        let length = self.history.len();
+        self.history[(self.position + length - 1 - age % length) % length]
+    }
+
+    fn trigger(&self, window: usize) -> usize {
+        find_trigger(|age| self.at(age)[0], window, self.history.len())
+    }
+
+    pub fn draw_oscilloscope(&self, width: f64, height: f64, c: Context, g: &mut G2d) {
+        let window = ((self.timebase_ms / 1000.0 * self.sample_rate) as usize).max(2);
+        let start = self.trigger(window);
+
+        // Graticule : 10 divisions horizontales, 8 verticales
+        let grid = [0.2, 0.2, 0.2, 1.0];
+        for i in 0..=10 {
+            let x = width * i as f64 / 10.0;
+            line(grid, 0.5, [x, 0.0, x, height], c.transform, g);
+        }
+        for i in 0..=8 {
+            let y = height * i as f64 / 8.0;
+            line(grid, 0.5, [0.0, y, width, y], c.transform, g);
+        }
+
+        // Au plus un point par pixel
+        let step = (window as f64 / width).max(1.0);
+        let points = (window as f64 / step) as usize;
+        let colors = [[0.0, 1.0, 0.0, 1.0], [1.0, 0.8, 0.0, 0.7]];
+        for (channel, color) in colors.iter().enumerate() {
+            let point = |i: usize| {
+                let age = start - ((i as f64 * step) as usize).min(window - 1);
+                let sample = self.at(age)[channel].clamp(-1.0, 1.0) as f64;
+                [
+                    width * i as f64 / points as f64,
+                    height / 2.0 * (1.0 - sample),
+                ]
+            };
+            for i in 1..points {
+                let ([x1, y1], [x2, y2]) = (point(i - 1), point(i));
+                line(*color, 0.8, [x1, y1, x2, y2], c.transform, g);
+            }
+        }
+    }
+
+    // Goniometer: mid (L+R) goes up, side (L-R) goes sideways, so a mono
+    // signal is a vertical line and out-of-phase channels a horizontal one.
+    // The bar at the bottom is the phase correlation, from -1 (left) to +1 (right).
+    pub fn draw_lissajous(&self, width: f64, height: f64, c: Context, g: &mut G2d) {
+        let size = width.min(height) * 0.45;
+        let (center_x, center_y) = (width / 2.0, height / 2.0);
+        let axes = [0.3, 0.3, 0.3, 1.0];
+        line(
+            axes,
+            0.5,
+            [
+                center_x - size,
+                center_y - size,
+                center_x + size,
+                center_y + size,
+            ],
+            c.transform,
+            g,
+        );
+        line(
+            axes,
+            0.5,
+            [
+                center_x + size,
+                center_y - size,
+                center_x - size,
+                center_y + size,
+            ],
+            c.transform,
+            g,
+        );
+        line(
+            axes,
+            0.5,
+            [center_x, center_y - size, center_x, center_y + size],
+            c.transform,
+            g,
+        );
+
+        let scale = size * std::f64::consts::FRAC_1_SQRT_2;
+        let point = |[left, right]: [f32; 2]| {
+            let (left, right) = (left.clamp(-1.0, 1.0) as f64, right.clamp(-1.0, 1.0) as f64);
+            [
+                center_x + (right - left) * scale,
+                center_y - (left + right) * scale,
+            ]
+        };
+        let mut previous = point(self.at(LISSAJOUS_POINTS));
+        for age in (0..LISSAJOUS_POINTS).rev() {
+            let frame = self.at(age);
+            let [x, y] = point(frame);
+            line(
+                [0.0, 1.0, 0.5, 0.6],
+                0.5,
+                [previous[0], previous[1], x, y],
+                c.transform,
+                g,
+            );
+            previous = [x, y];
+        }
+
+        let correlation = correlation((0..LISSAJOUS_POINTS).map(|age| self.at(age)));
+        let bar_y = height - 20.0;
+        rectangle(
+            [0.2, 0.2, 0.2, 1.0],
+            [center_x - size, bar_y, size * 2.0, 8.0],
+            c.transform,
+            g,
+        );
+        let color = if correlation < 0.0 {
+            [1.0, 0.0, 0.0, 1.0]
+        } else {
+            [0.0, 1.0, 0.0, 1.0]
+        };
+        rectangle(
+            color,
+            [center_x + correlation * size - 3.0, bar_y - 2.0, 6.0, 12.0],
+            c.transform,
+            g,
+        );
+    }
+}
+
+// Age of the first frame to display, `sample(age)` being the left channel
+// `age` frames before the newest one: the most recent rising zero crossing
+// that leaves a full window after it, or the latest window when there is none
+// within `length` frames (free run).
+fn find_trigger(sample: impl Fn(usize) -> f32, window: usize, length: usize) -> usize {
+    (window..length - 1)
+        .find(|&age| sample(age + 1) < 0.0 && sample(age) >= 0.0)
+        .unwrap_or(window)
+}
+
+// Phase correlation of stereo frames, from -1 (opposite) to +1 (identical);
+// 0 when a channel is silent.
+fn correlation(frames: impl Iterator<Item = [f32; 2]>) -> f64 {
+    let (mut lr, mut ll, mut rr) = (0.0, 0.0, 0.0);
+    for [left, right] in frames {
+        let (left, right) = (left as f64, right as f64);
+        lr += left * right;
+        ll += left * left;
+        rr += right * right;
+    }
+    if ll > 0.0 && rr > 0.0 {
+        lr / (ll * rr).sqrt()
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    // Sinus de 100 Hz a 48 kHz, 480 trames par periode, dephase de `phase`
+    fn sine(n: usize, phase: f32) -> f32 {
+        (2.0 * PI * n as f32 / 480.0 + phase).sin()
+    }
+
+    #[test]
+    fn trace_starts_on_a_rising_zero_crossing() {
+        let (mut sender, mut scope) = scope_channel(1, 48000, DEFAULT_TIMEBASE_MS);
+        let input: Vec<f32> = (0..10000).map(|n| sine(n, 1.0)).collect();
+        sender.push(&input);
+        scope.update();
+
+        let window = 960;
+        let start = scope.trigger(window);
+        // Le plus recent passage par zero qui laisse une fenetre complete
+        let newest = input.len() - 1;
+        let n = newest - start;
+        assert!(input[n - 1] < 0.0 && input[n] >= 0.0, "{}", n);
+        assert!(start >= window && start < window + 480, "{}", start);
+
+        // Sans passage par zero, l'affichage defile librement
+        let flat = |_: usize| 0.5;
+        assert_eq!(find_trigger(flat, window, 96000), window);
+    }
+
+    #[test]
+    fn zoom_stays_within_the_timebase_range() {
+        let (_, mut scope) = scope_channel(2, 48000, DEFAULT_TIMEBASE_MS);
+        scope.zoom(2.0);
+        assert_eq!(scope.timebase_ms(), 40.0);
+        for _ in 0..10 {
+            scope.zoom(2.0);
+        }
+        assert_eq!(scope.timebase_ms(), MAX_TIMEBASE_MS);
+        for _ in 0..20 {
+            scope.zoom(0.5);
+        }
+        assert_eq!(scope.timebase_ms(), MIN_TIMEBASE_MS);
+    }
+
+    #[test]
+    fn correlation_follows_the_phase() {
+        let frames = |phase: f32| (0..4800).map(move |n| [sine(n, 0.0), sine(n, phase)]);
+        assert!((correlation(frames(0.0)) - 1.0).abs() < 1e-9);
+        assert!((correlation(frames(PI)) + 1.0).abs() < 1e-9);
+        assert!(correlation(frames(PI / 2.0)).abs() < 1e-3);
+        assert_eq!(correlation((0..100).map(|_| [0.5, 0.0])), 0.0);
+    }
+}
@@ -0,0 +1,315 @@
+use piston_window::*;
+use rand::Rng;
+
+const PALETTE: [(&str, [f32; 4]); 8] = [
+    ("red", [1.0, 0.0, 0.0, 1.0]),
+    ("green", [0.0, 1.0, 0.0, 1.0]),
+    ("blue", [0.0, 0.0, 1.0, 1.0]),
+    ("yellow", [1.0, 1.0, 0.0, 1.0]),
+    ("magenta", [1.0, 0.0, 1.0, 1.0]),
+    ("cyan", [0.0, 1.0, 1.0, 1.0]),
+    ("gray", [0.5, 0.5, 0.5, 1.0]),
+    ("orange", [1.0, 0.5, 0.0, 1.0]),
+];
+// Une couleur `random` change toutes les 10 images
+const RANDOM_COLOR_FRAMES: u32 = 10;
+
+// A palette color drawn again every RANDOM_COLOR_FRAMES frames.
+pub struct RandomColor {
+    color: [f32; 4],
+    // Images restantes avant le prochain tirage
+    frames: u32,
+}
+
+impl RandomColor {
+    pub fn new() -> Self {
+        RandomColor {
+            color: PALETTE[0].1,
+            frames: 0,
+        }
+    }
+
+    // Call once per frame.
+    pub fn next(&mut self) -> [f32; 4] {
+        if self.frames == 0 {
+            self.color = PALETTE[rand::thread_rng().gen_range(0..PALETTE.len())].1;
+            self.frames = RANDOM_COLOR_FRAMES;
+        }
+        self.frames -= 1;
+        self.color
+    }
+}
+
+const DEFAULT_ATTACK: f64 = 0.03;
+const DEFAULT_RELEASE: f64 = 0.3;
+
+#[derive(Clone, Copy)]
+enum Shape {
+    Circle,
+    Square,
+    Triangle,
+}
+
+#[derive(Clone, Copy)]
+enum ShapeColor {
+    Fixed([f32; 4]),
+    Random,
+}
+
+// One `shape=<min Hz>:<max Hz>:<shape>[:<color>[:<rotation>]]` line.
+struct ShapeRange {
+    min: f32,
+    max: f32,
+    shape: Shape,
+    color: ShapeColor,
+    rotation: f64,
+}
+
+impl ShapeRange {
+    fn parse(spec: &str) -> Result<Self, String> {
+        let error = |reason: &str| format!("Invalid shape '{}': {}", spec, reason);
+        let parts: Vec<&str> = spec.split(':').collect();
+        if parts.len() < 3 || parts.len() > 5 {
+            return Err(error("expected min:max:shape[:color[:rotation]]"));
+        }
+        let (min, max) = match (parts[0].parse(), parts[1].parse()) {
+            (Ok(min), Ok(max)) if min < max => (min, max),
+            _ => return Err(error("bad pitch range")),
+        };
+        let shape = match parts[2] {
+            "circle" => Shape::Circle,
+            "square" => Shape::Square,
+            "triangle" => Shape::Triangle,
+            _ => return Err(error("unknown shape")),
+        };
+        let color = match parts.get(3).copied() {
+            None | Some("random") => ShapeColor::Random,
+            Some(name) => {
+                ShapeColor::Fixed(parse_color(name).ok_or_else(|| error("unknown color"))?)
+            }
+        };
+        let rotation = match parts.get(4) {
+            Some(rotation) => rotation.parse().map_err(|_| error("bad rotation"))?,
+            None => 0.0,
+        };
+        Ok(ShapeRange {
+            min,
+            max,
+            shape,
+            color,
+            rotation,
+        })
+    }
+}
+
+// Un nom de la palette ou une couleur #rrggbb
+fn parse_color(text: &str) -> Option<[f32; 4]> {
+    if let Some(hex) = text.strip_prefix('#') {
+        let value = u32::from_str_radix(hex, 16)
+            .ok()
+            .filter(|_| hex.len() == 6)?;
+        let channel = |shift: u32| ((value >> shift) & 0xff) as f32 / 255.0;
+        return Some([channel(16), channel(8), channel(0), 1.0]);
+    }
+    PALETTE
+        .iter()
+        .find(|(name, _)| *name == text)
+        .map(|(_, color)| *color)
+}
+
+// Follows a target quickly when it rises (attack) and slowly when it falls (release).
+#[derive(Clone, Copy, Default)]
+struct Envelope {
+    value: f64,
+}
+
+impl Envelope {
+    fn follow(&mut self, target: f64, attack: f64, release: f64) {
+        let coefficient = if target > self.value { attack } else { release };
+        self.value += coefficient * (target - self.value);
+    }
+}
+
+// The pitch picks a shape, a color and a rotation from the mapping table;
+// the level sets the size. Size, color and rotation are smoothed.
+pub struct ShapeVisual {
+    ranges: Vec<ShapeRange>,
+    attack: f64,
+    release: f64,
+    current: usize,
+    radius: Envelope,
+    color: [Envelope; 4],
+    rotation: Envelope,
+    random_color: RandomColor,
+}
+
+impl ShapeVisual {
+    // Reads the shape=, attack= and release= options. Without shape= lines,
+    // circle under 200 Hz, square up to 400 Hz and triangle above, in random colors.
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut ranges = args
+            .iter()
+            .filter(|arg| arg.starts_with("shape="))
+            .filter_map(|arg| arg.split('=').nth(1))
+            .map(ShapeRange::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        if ranges.is_empty() {
+            ranges = ["0:200:circle", "200:400:square", "400:100000:triangle"]
+                .into_iter()
+                .map(ShapeRange::parse)
+                .collect::<Result<_, _>>()?;
+        }
+
+        let seconds = |key: &str, default: f64| match args
+            .iter()
+            .find(|arg| arg.starts_with(key))
+            .and_then(|arg| arg.split('=').nth(1))
+        {
+            Some(text) => text
+                .parse::<f64>()
+                .ok()
+                .filter(|&value| value >= 0.0)
+                .ok_or_else(|| format!("Invalid {}{}", key, text)),
+            None => Ok(default),
+        };
+
+        Ok(ShapeVisual {
+            ranges,
+            attack: seconds("attack=", DEFAULT_ATTACK)?,
+            release: seconds("release=", DEFAULT_RELEASE)?,
+            current: 0,
+            radius: Envelope::default(),
+            color: [Envelope::default(); 4],
+            rotation: Envelope::default(),
+            random_color: RandomColor::new(),
+        })
+    }
+
+    // `dt` is the time since the last update, in seconds.
+    pub fn update(&mut self, decibels: f32, pitch: f32, dt: f64) {
+        // Hors de toute plage, la forme precedente est conservee
+        if let Some(index) = self
+            .ranges
+            .iter()
+            .position(|range| (range.min..range.max).contains(&pitch))
+        {
+            self.current = index;
+        }
+        let random_color = self.random_color.next();
+
+        // Coefficients pour des constantes de temps en secondes
+        let coefficient = |time: f64| {
+            if time > 0.0 {
+                1.0 - (-dt / time).exp()
+            } else {
+                1.0
+            }
+        };
+        let (attack, release) = (coefficient(self.attack), coefficient(self.release));
+
+        let range = &self.ranges[self.current];
+        let radius = ((decibels + 60.0).max(0.0) * 20.0) as f64; // Ajuster l'échelle pour rendre la taille plus sensible aux décibels
+        self.radius.follow(radius, attack, release);
+        let color = match range.color {
+            ShapeColor::Fixed(color) => color,
+            ShapeColor::Random => random_color,
+        };
+        for (envelope, target) in self.color.iter_mut().zip(color) {
+            envelope.follow(target as f64, attack, release);
+        }
+        self.rotation.follow(range.rotation, attack, release);
+    }
+
+    pub fn draw(&self, width: f64, height: f64, c: Context, g: &mut G2d) {
+        let radius = self.radius.value;
+        let color = self.color.map(|envelope| envelope.value as f32);
+        let transform = c
+            .transform
+            .trans((width / 2.0).floor(), (height / 2.0).floor())
+            .rot_deg(self.rotation.value);
+        let square = [-radius, -radius, radius * 2.0, radius * 2.0];
+
+        match self.ranges[self.current].shape {
+            Shape::Circle => ellipse(color, square, transform, g),
+            Shape::Square => rectangle(color, square, transform, g),
+            Shape::Triangle => {
+                let points = [
+                    [0.0, -radius],    // Top vertex
+                    [-radius, radius], // Bottom left vertex
+                    [radius, radius],  // Bottom right vertex
+                ];
+                polygon(color, &points, transform, g);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_shape_ranges() {
+        let range = ShapeRange::parse("150:300:square:#ff8000:45").unwrap();
+        assert_eq!((range.min, range.max, range.rotation), (150.0, 300.0, 45.0));
+        assert!(matches!(range.shape, Shape::Square));
+        assert!(
+            matches!(range.color, ShapeColor::Fixed(color) if color == [1.0, 128.0 / 255.0, 0.0, 1.0])
+        );
+
+        let range = ShapeRange::parse("300:800:triangle").unwrap();
+        assert!(matches!(range.shape, Shape::Triangle));
+        assert!(matches!(range.color, ShapeColor::Random));
+        assert_eq!(range.rotation, 0.0);
+        let range = ShapeRange::parse("0:200:circle:cyan").unwrap();
+        assert!(matches!(
+            range.color,
+            ShapeColor::Fixed([0.0, 1.0, 1.0, 1.0])
+        ));
+
+        for spec in [
+            "100:200",
+            "100:200:circle:red:0:extra",
+            "200:100:circle",
+            "low:200:circle",
+            "100:200:hexagon",
+            "100:200:circle:purple",
+            "100:200:circle:#ff80",
+            "100:200:circle:#gg8000",
+            "100:200:circle:red:left",
+        ] {
+            assert!(ShapeRange::parse(spec).is_err(), "{}", spec);
+        }
+        let args = vec![String::from("shape=100:200:hexagon")];
+        assert!(ShapeVisual::from_args(&args).is_err());
+        assert_eq!(ShapeVisual::from_args(&[]).unwrap().ranges.len(), 3);
+    }
+
+    #[test]
+    fn envelope_uses_the_attack_and_release_times() {
+        let args = vec![String::from("attack=0.01"), String::from("release=0.5")];
+        let mut visual = ShapeVisual::from_args(&args).unwrap();
+        // 0 dBFS donne un rayon de 1200 : 63 % du chemin apres une constante de temps
+        visual.update(0.0, 100.0, 0.01);
+        let rise = visual.radius.value / 1200.0;
+        assert!((rise - 0.632).abs() < 0.001, "{}", rise);
+        for _ in 0..20 {
+            visual.update(0.0, 100.0, 0.01);
+        }
+        assert!(visual.radius.value > 1199.0);
+
+        // La retombee est 50 fois plus lente : 63 % de la descente apres 0.5 s
+        let start = visual.radius.value;
+        visual.update(-60.0, 100.0, 0.01);
+        assert!(
+            visual.radius.value > 0.97 * start,
+            "{}",
+            visual.radius.value
+        );
+        for _ in 0..49 {
+            visual.update(-60.0, 100.0, 0.01);
+        }
+        let fall = 1.0 - visual.radius.value / start;
+        assert!((fall - 0.632).abs() < 0.001, "{}", fall);
+    }
+}
@@ -0,0 +1,331 @@
+use crate::device::{self, InputOptions};
+use crate::generator::{Generator, Signal};
+use crate::offline;
+use cpal::traits::{DeviceTrait, StreamTrait};
+use cpal::{Device, InputCallbackInfo, Stream, SupportedStreamConfig};
+use hound::WavReader;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+// Taille des blocs livres par les sources sans carte son
+const BLOCK_FRAMES: usize = 512;
+const DEFAULT_SAMPLE_RATE: u32 = 48000;
+const DEFAULT_STEP_SECONDS: f64 = 1.0;
+
+// Remplit un bloc entrelace, pour les sources sans carte son
+type Fill = Box<dyn FnMut(&mut [f32]) + Send>;
+
+// Where the audio comes from, picked with `source=`:
+// `device` (default), `wav:<path>`, `synth:<signal>[@<seconds>][,...]` or `null`.
+// Everything but the device runs without sound hardware, in real time.
+pub enum AudioSource {
+    Device(Device, SupportedStreamConfig),
+    Wav {
+        path: String,
+        samples: Vec<f32>,
+        channels: u16,
+        sample_rate: u32,
+    },
+    Synth {
+        script: Box<Script>,
+        channels: u16,
+        sample_rate: u32,
+    },
+    Null {
+        channels: u16,
+        sample_rate: u32,
+    },
+}
+
+// A looped sequence of test signals, each played for its duration.
+pub struct Script {
+    spec: String,
+    steps: Vec<(Signal, f64)>,
+    level: f32,
+    current: usize,
+    remaining: usize,
+    generator: Option<Generator>,
+}
+
+impl Script {
+    // <signal>[@<seconds>], comma separated, e.g. sine:220@2,pink@1,impulse
+    pub fn parse(spec: &str, level: f32) -> Result<Self, String> {
+        let steps = spec
+            .split(',')
+            .map(|step| {
+                let (signal, seconds) = match step.split_once('@') {
+                    Some((signal, seconds)) => (
+                        signal,
+                        seconds
+                            .parse::<f64>()
+                            .ok()
+                            .filter(|&seconds| seconds > 0.0)
+                            .ok_or_else(|| format!("Invalid duration in '{}'", step))?,
+                    ),
+                    None => (step, DEFAULT_STEP_SECONDS),
+                };
+                Ok((Signal::parse(signal)?, seconds))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(Script {
+            spec: spec.to_string(),
+            steps,
+            level,
+            current: 0,
+            remaining: 0,
+            generator: None,
+        })
+    }
+
+    pub fn fill(&mut self, data: &mut [f32], channels: usize, sample_rate: u32) {
+        for frame in data.chunks_mut(channels) {
+            if self.remaining == 0 || self.generator.is_none() {
+                if self.generator.is_some() {
+                    self.current = (self.current + 1) % self.steps.len();
+                }
+                let (signal, seconds) = self.steps[self.current];
+                self.generator = Some(Generator::new(signal, sample_rate, self.level));
+                self.remaining = ((seconds * sample_rate as f64) as usize).max(1);
+            }
+            frame.fill(self.generator.as_mut().unwrap().next_sample());
+            self.remaining -= 1;
+        }
+    }
+}
+
+// Keeps the source running; dropping it stops the stream or the thread.
+pub struct RunningSource {
+    // Le flux cpal s'arrete quand il est libere
+    _stream: Option<Stream>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for RunningSource {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+impl AudioSource {
+    pub fn from_args(args: &[String], level: f32) -> Result<Self, String> {
+        let options = InputOptions::from_args(args);
+        let channels = options.channels.unwrap_or(1).max(1);
+        let sample_rate = options.sample_rate.unwrap_or(DEFAULT_SAMPLE_RATE);
+        let spec = args
+            .iter()
+            .find(|arg| arg.starts_with("source="))
+            .and_then(|arg| arg.split('=').nth(1))
+            .unwrap_or("device");
+
+        if spec == "device" {
+            let (device, config) = device::open_input(&options)?;
+            return Ok(AudioSource::Device(device, config));
+        }
+        if spec == "null" {
+            return Ok(AudioSource::Null {
+                channels,
+                sample_rate,
+            });
+        }
+        if let Some(path) = spec.strip_prefix("wav:") {
+            let reader =
+                WavReader::open(path).map_err(|err| format!("Failed to open {}: {}", path, err))?;
+            let wav = reader.spec();
+            let samples =
+                offline::read_samples(reader).map_err(|err| format!("{}: {}", path, err))?;
+            if samples.is_empty() {
+                return Err(format!("{}: no samples", path));
+            }
+            return Ok(AudioSource::Wav {
+                path: path.to_string(),
+                samples,
+                channels: wav.channels.max(1),
+                sample_rate: wav.sample_rate,
+            });
+        }
+        if let Some(script) = spec.strip_prefix("synth:") {
+            return Ok(AudioSource::Synth {
+                script: Box::new(Script::parse(script, level)?),
+                channels,
+                sample_rate,
+            });
+        }
+        Err(format!(
+            "Unknown source '{}', expected device, wav:<path>, synth:<signals> or null",
+            spec
+        ))
+    }
+
+    pub fn channels(&self) -> u16 {
+        match self {
+            AudioSource::Device(_, config) => config.channels(),
+            AudioSource::Wav { channels, .. }
+            | AudioSource::Synth { channels, .. }
+            | AudioSource::Null { channels, .. } => *channels,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        match self {
+            AudioSource::Device(_, config) => config.sample_rate().0,
+            AudioSource::Wav { sample_rate, .. }
+            | AudioSource::Synth { sample_rate, .. }
+            | AudioSource::Null { sample_rate, .. } => *sample_rate,
+        }
+    }
+
+    // Description printed at startup.
+    pub fn describe(&self) -> String {
+        let name = match self {
+            AudioSource::Device(device, _) => device.name().unwrap_or_default(),
+            AudioSource::Wav { path, .. } => format!("wav:{}", path),
+            AudioSource::Synth { script, .. } => format!("synth:{}", script.spec),
+            AudioSource::Null { .. } => String::from("null"),
+        };
+        let format = match self {
+            AudioSource::Device(_, config) => format!(", {:?}", config.sample_format()),
+            _ => String::new(),
+        };
+        format!(
+            "{} ({} channels, {} Hz{})",
+            name,
+            self.channels(),
+            self.sample_rate(),
+            format
+        )
+    }
+
+    // Hands interleaved f32 samples to the callback, from the cpal callback
+    // for a device and from a thread paced on the sample rate otherwise.
+    // `InputCallbackInfo` is only there for a device.
+    pub fn start<D>(self, mut data_callback: D) -> Result<RunningSource, String>
+    where
+        D: FnMut(&[f32], Option<&InputCallbackInfo>) + Send + 'static,
+    {
+        let channels = self.channels() as usize;
+        let sample_rate = self.sample_rate();
+        let mut fill: Fill = match self {
+            AudioSource::Device(device, config) => {
+                let stream = device::build_input_stream(
+                    &device,
+                    &config,
+                    move |data: &[f32], info: &InputCallbackInfo| data_callback(data, Some(info)),
+                    |err| eprintln!("Error: {:?}", err),
+                )?;
+                stream
+                    .play()
+                    .map_err(|err| format!("Failed to start input stream: {}", err))?;
+                return Ok(RunningSource {
+                    _stream: Some(stream),
+                    stop: Arc::new(AtomicBool::new(false)),
+                    thread: None,
+                });
+            }
+            AudioSource::Wav { samples, .. } => {
+                // Le fichier est rejoue en boucle
+                let mut position = 0;
+                Box::new(move |data: &mut [f32]| {
+                    for sample in data {
+                        *sample = samples[position];
+                        position = (position + 1) % samples.len();
+                    }
+                })
+            }
+            AudioSource::Synth { mut script, .. } => {
+                Box::new(move |data: &mut [f32]| script.fill(data, channels, sample_rate))
+            }
+            AudioSource::Null { .. } => Box::new(|data: &mut [f32]| data.fill(0.0)),
+        };
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_clone = Arc::clone(&stop);
+        let thread = std::thread::spawn(move || {
+            let mut block = vec![0.0; BLOCK_FRAMES * channels];
+            let block_duration = Duration::from_secs_f64(BLOCK_FRAMES as f64 / sample_rate as f64);
+            let mut next = Instant::now();
+            while !stop_clone.load(Ordering::Relaxed) {
+                fill(&mut block);
+                data_callback(&block, None);
+                next += block_duration;
+                std::thread::sleep(next.saturating_duration_since(Instant::now()));
+            }
+        });
+        Ok(RunningSource {
+            _stream: None,
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::{Analyzer, FRAME_SIZE, HOP_SIZE};
+    use std::sync::Mutex;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn script_plays_its_steps_in_order() {
+        let mut script = Script::parse("sine:220@0.5,sine:880@0.5", -6.0).unwrap();
+        let mut analyzer = Analyzer::new(FRAME_SIZE, 48000.0);
+        let mut hop = vec![0.0; HOP_SIZE];
+        let mut pitches = Vec::new();
+        // 2 s : les deux pas, deux fois
+        for _ in 0..(2 * 48000 / HOP_SIZE) {
+            script.fill(&mut hop, 1, 48000);
+            pitches.push(analyzer.push(&hop).pitch);
+        }
+        let hops_per_step = 24000 / HOP_SIZE;
+        for (step, expected) in [220.0, 880.0, 220.0, 880.0].into_iter().enumerate() {
+            // Milieu du pas, loin des transitions
+            let pitch = pitches[step * hops_per_step + hops_per_step / 2];
+            assert!((pitch - expected).abs() < 2.0, "step {}: {}", step, pitch);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_sources() {
+        assert!(AudioSource::from_args(&args(&["source=synth:square"]), -18.0).is_err());
+        assert!(AudioSource::from_args(&args(&["source=synth:sine@0"]), -18.0).is_err());
+        assert!(AudioSource::from_args(&args(&["source=radio"]), -18.0).is_err());
+    }
+
+    #[test]
+    fn synth_source_runs_without_hardware() {
+        let source = AudioSource::from_args(
+            &args(&["source=synth:sine:1000", "channels=2", "sample_rate=8000"]),
+            -6.0,
+        )
+        .unwrap();
+        assert_eq!((source.channels(), source.sample_rate()), (2, 8000));
+
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let received_clone = Arc::clone(&received);
+        let running = source
+            .start(move |data, info| {
+                assert!(info.is_none());
+                received_clone.lock().unwrap().extend_from_slice(data);
+            })
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(200));
+        drop(running);
+
+        let received = received.lock().unwrap();
+        // Environ 1600 trames en 200 ms
+        assert!(received.len() >= BLOCK_FRAMES * 2, "{}", received.len());
+        // Meme echantillon sur les deux canaux, crete a -6 dBFS
+        assert!(received.chunks(2).all(|frame| frame[0] == frame[1]));
+        let peak = received.iter().fold(0.0f32, |peak, v| peak.max(v.abs()));
+        assert!((peak - 0.5).abs() < 0.01, "{}", peak);
+    }
+}
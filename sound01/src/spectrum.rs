@@ -0,0 +1,269 @@
+use piston_window::*;
+use std::collections::VecDeque;
+
+pub const BARS: usize = 48;
+const WATERFALL_BANDS: usize = 96;
+const WATERFALL_ROWS: usize = 100;
+
+const MIN_FREQUENCY: f32 = 30.0;
+const MAX_FREQUENCY: f32 = 16000.0;
+// Plage affichee, 0 dB correspondant a un sinus pleine echelle
+const MIN_DB: f32 = -90.0;
+const MAX_DB: f32 = 0.0;
+
+// Retombee des barres et des cretes, par image
+const BAR_FALL: f32 = 0.03;
+const PEAK_FALL: f32 = 0.005;
+const PEAK_HOLD_FRAMES: u32 = 30;
+
+// Groups FFT bins into bands spaced evenly on a log-frequency axis.
+pub struct LogBands {
+    ranges: Vec<(usize, usize)>,
+    reference: f32,
+}
+
+impl LogBands {
+    pub fn new(count: usize, frame_size: usize, sample_rate: f32) -> Self {
+        let bin_width = sample_rate / frame_size as f32;
+        let max_frequency = MAX_FREQUENCY.min(sample_rate / 2.0);
+        let ratio = (max_frequency / MIN_FREQUENCY).ln();
+        let bin = |i: usize| {
+            let frequency = MIN_FREQUENCY * (ratio * i as f32 / count as f32).exp();
+            ((frequency / bin_width).round() as usize).clamp(1, frame_size / 2)
+        };
+        // Au moins un bin par bande, meme en bas du spectre ou les bandes sont plus etroites qu'un bin
+        let ranges = (0..count)
+            .map(|i| {
+                let start = bin(i);
+                (start, bin(i + 1).max(start + 1))
+            })
+            .collect();
+        LogBands {
+            ranges,
+            // Magnitude d'un sinus d'amplitude 1 sous une fenetre de Hann
+            reference: frame_size as f32 / 4.0,
+        }
+    }
+
+    // Level of each band scaled from MIN_DB..MAX_DB to 0..1.
+    pub fn levels(&self, magnitudes: &[f32], levels: &mut [f32]) {
+        for (level, &(start, end)) in levels.iter_mut().zip(&self.ranges) {
+            let peak = magnitudes
+                .get(start..end.min(magnitudes.len()))
+                .map_or(0.0, |bins| bins.iter().copied().fold(0.0, f32::max));
+            let db = 20.0 * (peak / self.reference + 1e-9).log10();
+            *level = ((db - MIN_DB) / (MAX_DB - MIN_DB)).clamp(0.0, 1.0);
+        }
+    }
+}
+
+// Spectrum bars with peak hold: each peak stays up for a while, then falls slowly.
+pub struct Bars {
+    bands: LogBands,
+    current: [f32; BARS],
+    levels: [f32; BARS],
+    peaks: [f32; BARS],
+    hold: [u32; BARS],
+}
+
+impl Bars {
+    pub fn new(frame_size: usize, sample_rate: f32) -> Self {
+        Bars {
+            bands: LogBands::new(BARS, frame_size, sample_rate),
+            current: [0.0; BARS],
+            levels: [0.0; BARS],
+            peaks: [0.0; BARS],
+            hold: [0; BARS],
+        }
+    }
+
+    pub fn update(&mut self, magnitudes: &[f32]) {
+        self.bands.levels(magnitudes, &mut self.current);
+        for i in 0..BARS {
+            let level = self.current[i];
+            self.levels[i] = level.max(self.levels[i] - BAR_FALL);
+            if level >= self.peaks[i] {
+                self.peaks[i] = level;
+                self.hold[i] = PEAK_HOLD_FRAMES;
+            } else if self.hold[i] > 0 {
+                self.hold[i] -= 1;
+            } else {
+                self.peaks[i] = (self.peaks[i] - PEAK_FALL).max(level);
+            }
+        }
+    }
+
+    pub fn draw(&self, width: f64, height: f64, c: Context, g: &mut G2d) {
+        let cell = width / BARS as f64;
+        for i in 0..BARS {
+            let x = i as f64 * cell + 1.0;
+            let level = self.levels[i] as f64;
+            rectangle(
+                color_map(self.levels[i]),
+                [x, height * (1.0 - level), cell - 2.0, height * level],
+                c.transform,
+                g,
+            );
+            let peak_y = height * (1.0 - self.peaks[i] as f64);
+            rectangle([1.0; 4], [x, peak_y, cell - 2.0, 2.0], c.transform, g);
+        }
+    }
+}
+
+// Scrolling spectrogram, newest row at the top.
+pub struct Waterfall {
+    bands: LogBands,
+    rows: VecDeque<[f32; WATERFALL_BANDS]>,
+}
+
+impl Waterfall {
+    pub fn new(frame_size: usize, sample_rate: f32) -> Self {
+        Waterfall {
+            bands: LogBands::new(WATERFALL_BANDS, frame_size, sample_rate),
+            rows: VecDeque::with_capacity(WATERFALL_ROWS),
+        }
+    }
+
+    pub fn update(&mut self, magnitudes: &[f32]) {
+        let mut row = if self.rows.len() == WATERFALL_ROWS {
+            self.rows.pop_back().unwrap()
+        } else {
+            [0.0; WATERFALL_BANDS]
+        };
+        self.bands.levels(magnitudes, &mut row);
+        self.rows.push_front(row);
+    }
+
+    pub fn draw(&self, width: f64, height: f64, c: Context, g: &mut G2d) {
+        let cell_width = width / WATERFALL_BANDS as f64;
+        let cell_height = height / WATERFALL_ROWS as f64;
+        for (y, row) in self.rows.iter().enumerate() {
+            for (x, &level) in row.iter().enumerate() {
+                // Le fond est deja noir
+                if level <= 0.0 {
+                    continue;
+                }
+                rectangle(
+                    color_map(level),
+                    [
+                        x as f64 * cell_width,
+                        y as f64 * cell_height,
+                        cell_width + 0.5,
+                        cell_height + 0.5,
+                    ],
+                    c.transform,
+                    g,
+                );
+            }
+        }
+    }
+}
+
+// Noir, bleu, magenta, rouge, jaune puis blanc pour un niveau de 0 a 1
+fn color_map(level: f32) -> [f32; 4] {
+    const STOPS: [[f32; 3]; 6] = [
+        [0.0, 0.0, 0.0],
+        [0.1, 0.0, 0.6],
+        [0.7, 0.0, 0.7],
+        [1.0, 0.1, 0.1],
+        [1.0, 0.9, 0.0],
+        [1.0, 1.0, 1.0],
+    ];
+    let position = level.clamp(0.0, 1.0) * (STOPS.len() - 1) as f32;
+    let index = (position as usize).min(STOPS.len() - 2);
+    let t = position - index as f32;
+    let (low, high) = (STOPS[index], STOPS[index + 1]);
+    [
+        low[0] + t * (high[0] - low[0]),
+        low[1] + t * (high[1] - low[1]),
+        low[2] + t * (high[2] - low[2]),
+        1.0,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::{Analyzer, FRAME_SIZE, HOP_SIZE};
+
+    // Sinus d'un nombre entier de periodes, pour que sa moyenne soit nulle
+    fn sine_magnitudes(bin: usize) -> Vec<f32> {
+        let frequency = bin as f32 * 48000.0 / FRAME_SIZE as f32;
+        let mut analyzer = Analyzer::new(FRAME_SIZE, 48000.0);
+        let sine: Vec<f32> = (0..FRAME_SIZE)
+            .map(|n| (2.0 * std::f32::consts::PI * frequency * n as f32 / 48000.0).sin())
+            .collect();
+        for hop in sine.chunks(HOP_SIZE) {
+            analyzer.push(hop);
+        }
+        analyzer.magnitudes().to_vec()
+    }
+
+    #[test]
+    fn bands_cover_the_range_without_gaps() {
+        let bands = LogBands::new(BARS, FRAME_SIZE, 48000.0);
+        let bin_width = 48000.0 / FRAME_SIZE as f32;
+        assert_eq!(
+            bands.ranges[0].0,
+            (MIN_FREQUENCY / bin_width).round() as usize
+        );
+        assert_eq!(
+            bands.ranges[BARS - 1].1,
+            (MAX_FREQUENCY / bin_width).round() as usize
+        );
+        for (i, &(start, end)) in bands.ranges.iter().enumerate() {
+            assert!(start < end, "band {} is empty", i);
+            if i > 0 {
+                let (previous_start, previous_end) = bands.ranges[i - 1];
+                assert!(start >= previous_start && end >= previous_end, "band {}", i);
+                assert!(start <= previous_end, "gap before band {}", i);
+            }
+        }
+    }
+
+    #[test]
+    fn a_sine_lands_in_its_band() {
+        let bands = LogBands::new(BARS, FRAME_SIZE, 48000.0);
+        // Environ 1 kHz
+        let bin = 43.0;
+        let mut levels = [0.0; BARS];
+        bands.levels(&sine_magnitudes(43), &mut levels);
+
+        let band = bands
+            .ranges
+            .iter()
+            .position(|&(start, end)| (start as f32..end as f32).contains(&bin))
+            .unwrap();
+        // Pleine echelle dans sa bande, presque rien a plus d'une octave
+        assert!(levels[band] > 0.98, "{}", levels[band]);
+        for (&(start, end), level) in bands.ranges.iter().zip(levels) {
+            if (end as f32) < bin / 2.0 || start as f32 > bin * 2.0 {
+                assert!(level < 0.2, "{}..{}: {}", start, end, level);
+            }
+        }
+    }
+
+    #[test]
+    fn peaks_hold_then_fall() {
+        let mut bars = Bars::new(FRAME_SIZE, 48000.0);
+        let magnitudes = sine_magnitudes(43);
+        bars.update(&magnitudes);
+        let band = (0..BARS)
+            .max_by(|&a, &b| bars.peaks[a].total_cmp(&bars.peaks[b]))
+            .unwrap();
+        let peak = bars.peaks[band];
+
+        // Silence : la barre retombe aussitot, la crete tient PEAK_HOLD_FRAMES images
+        let silence = vec![0.0; magnitudes.len()];
+        for _ in 0..PEAK_HOLD_FRAMES {
+            bars.update(&silence);
+            assert_eq!(bars.peaks[band], peak);
+        }
+        assert!(bars.levels[band] < peak - 10.0 * BAR_FALL);
+        for frame in 1..=10 {
+            bars.update(&silence);
+            let expected = peak - frame as f32 * PEAK_FALL;
+            assert!((bars.peaks[band] - expected).abs() < 1e-5);
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use crate::analysis::Analysis;
+use crate::MIN_CONFIDENCE;
+use hound::{SampleFormat, WavSpec, WavWriter};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+const DEFAULT_THRESHOLD: f32 = -40.0;
+const DEFAULT_PREROLL: f64 = 0.5;
+const DEFAULT_HOLD: f64 = 1.5;
+// Plage de la fondamentale d'une voix parlee
+const VOICE_MIN_PITCH: f32 = 70.0;
+const VOICE_MAX_PITCH: f32 = 500.0;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Source {
+    // Niveau RMS au-dessus du seuil
+    Level,
+    // Niveau au-dessus du seuil et hauteur stable dans la tessiture de la voix
+    Voice,
+}
+
+struct Clip {
+    path: String,
+    writer: WavWriter<BufWriter<File>>,
+    frames: u64,
+}
+
+// Writes a numbered WAV file each time the input becomes active, starting
+// with the pre-roll kept from just before, and closes it once the input has
+// been quiet for the hold time.
+pub struct TriggerRecorder {
+    prefix: String,
+    source: Source,
+    threshold: f32,
+    spec: WavSpec,
+    preroll: VecDeque<f32>,
+    preroll_samples: usize,
+    hold_frames: u64,
+    quiet_frames: u64,
+    clip: Option<Clip>,
+    next_number: u32,
+}
+
+impl TriggerRecorder {
+    // trigger=<prefix> [trigger_on=level|voice] [threshold=<dB>] [preroll=<s>] [hold=<s>]
+    pub fn from_args(args: &[String], channels: usize, sample_rate: u32) -> Result<Self, String> {
+        let value = |key: &str| {
+            args.iter()
+                .find(|arg| arg.starts_with(key))
+                .and_then(|arg| arg.split('=').nth(1))
+        };
+        let number = |key: &str, default: f64| match value(key) {
+            Some(text) => text
+                .parse::<f64>()
+                .map_err(|_| format!("Invalid {}{}", key, text)),
+            None => Ok(default),
+        };
+
+        let prefix = value("trigger=")
+            .filter(|prefix| !prefix.is_empty())
+            .unwrap_or("clip")
+            .to_string();
+        let source = match value("trigger_on=").unwrap_or("level") {
+            "level" => Source::Level,
+            "voice" => Source::Voice,
+            other => return Err(format!("Unknown trigger_on '{}'", other)),
+        };
+        let threshold = number("threshold=", DEFAULT_THRESHOLD as f64)? as f32;
+        let preroll = number("preroll=", DEFAULT_PREROLL)?.max(0.0);
+        let hold = number("hold=", DEFAULT_HOLD)?.max(0.0);
+
+        let preroll_samples = (preroll * sample_rate as f64) as usize * channels;
+        Ok(TriggerRecorder {
+            prefix,
+            source,
+            threshold,
+            spec: WavSpec {
+                channels: channels as u16,
+                sample_rate,
+                bits_per_sample: 32,
+                sample_format: SampleFormat::Float,
+            },
+            preroll: VecDeque::with_capacity(preroll_samples),
+            preroll_samples,
+            hold_frames: (hold * sample_rate as f64) as u64,
+            quiet_frames: 0,
+            clip: None,
+            next_number: 1,
+        })
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.clip.is_some()
+    }
+
+    fn is_active(&self, analysis: &Analysis) -> bool {
+        let loud = analysis.decibels >= self.threshold;
+        match self.source {
+            Source::Level => loud,
+            Source::Voice => {
+                loud && analysis.confidence >= MIN_CONFIDENCE
+                    && (VOICE_MIN_PITCH..=VOICE_MAX_PITCH).contains(&analysis.pitch)
+            }
+        }
+    }
+
+    // Next <prefix>_NNN.wav that does not exist yet, so a new run keeps the old clips.
+    fn next_path(&mut self) -> String {
+        loop {
+            let path = format!("{}_{:03}.wav", self.prefix, self.next_number);
+            self.next_number += 1;
+            if !Path::new(&path).exists() {
+                return path;
+            }
+        }
+    }
+
+    pub fn push(&mut self, hop: &[f32], analysis: &Analysis) -> Result<(), String> {
+        let active = self.is_active(analysis);
+        let frames = (hop.len() / self.spec.channels as usize) as u64;
+
+        if self.clip.is_none() {
+            if !active {
+                // Pre-roll glissant
+                self.preroll.extend(hop);
+                let excess = self.preroll.len().saturating_sub(self.preroll_samples);
+                self.preroll.drain(..excess);
+                return Ok(());
+            }
+            let path = self.next_path();
+            let writer = WavWriter::create(&path, self.spec)
+                .map_err(|err| format!("Failed to create {}: {}", path, err))?;
+            println!("Triggered: recording {}", path);
+            let mut clip = Clip {
+                path,
+                writer,
+                frames: (self.preroll.len() / self.spec.channels as usize) as u64,
+            };
+            for sample in self.preroll.drain(..) {
+                clip.write(sample)?;
+            }
+            self.clip = Some(clip);
+            self.quiet_frames = 0;
+        }
+
+        let clip = self.clip.as_mut().unwrap();
+        for &sample in hop {
+            clip.write(sample)?;
+        }
+        clip.frames += frames;
+
+        if active {
+            self.quiet_frames = 0;
+        } else {
+            self.quiet_frames += frames;
+            if self.quiet_frames >= self.hold_frames {
+                self.finish()?;
+            }
+        }
+        Ok(())
+    }
+
+    // Closes the clip being recorded, if any.
+    pub fn finish(&mut self) -> Result<(), String> {
+        let Some(clip) = self.clip.take() else {
+            return Ok(());
+        };
+        let seconds = clip.frames as f64 / self.spec.sample_rate as f64;
+        clip.writer
+            .finalize()
+            .map_err(|err| format!("Failed to write {}: {}", clip.path, err))?;
+        println!("Saved {} ({:.1} s)", clip.path, seconds);
+        Ok(())
+    }
+}
+
+impl Clip {
+    fn write(&mut self, sample: f32) -> Result<(), String> {
+        self.writer
+            .write_sample(sample)
+            .map_err(|err| format!("Failed to write {}: {}", self.path, err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn analysis(decibels: f32) -> Analysis {
+        Analysis {
+            decibels,
+            peak_decibels: decibels,
+            pitch: 0.0,
+            confidence: 0.0,
+        }
+    }
+
+    #[test]
+    fn records_preroll_and_hold_into_a_new_file() {
+        let prefix = std::env::temp_dir().join("sound01_trigger_test");
+        let prefix = prefix.to_str().unwrap();
+        let taken = format!("{}_001.wav", prefix);
+        let path = format!("{}_002.wav", prefix);
+        std::fs::write(&taken, b"old clip").unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        // 1000 Hz mono, hops de 100 trames : 250 trames de pre-roll, 300 de maintien
+        let args = vec![
+            format!("trigger={}", prefix),
+            String::from("threshold=-20"),
+            String::from("preroll=0.25"),
+            String::from("hold=0.3"),
+        ];
+        let mut recorder = TriggerRecorder::from_args(&args, 1, 1000).unwrap();
+
+        // Chaque hop calme porte son numero pour reconnaitre le pre-roll
+        for index in 0..5 {
+            let hop = vec![index as f32 / 100.0; 100];
+            recorder.push(&hop, &analysis(-60.0)).unwrap();
+            assert!(!recorder.is_recording());
+        }
+        recorder.push(&[0.5; 100], &analysis(-6.0)).unwrap();
+        assert!(recorder.is_recording());
+        for _ in 0..2 {
+            recorder.push(&[0.0; 100], &analysis(-60.0)).unwrap();
+            assert!(recorder.is_recording());
+        }
+        recorder.push(&[0.0; 100], &analysis(-60.0)).unwrap();
+        assert!(!recorder.is_recording());
+
+        assert_eq!(std::fs::read(&taken).unwrap(), b"old clip");
+        let samples: Vec<f32> = hound::WavReader::open(&path)
+            .unwrap()
+            .samples::<f32>()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(samples.len(), 250 + 100 + 300);
+        // Le pre-roll commence au milieu du hop 2 et s'arrete au franchissement
+        assert_eq!(samples[0], 0.02);
+        assert_eq!(samples[50], 0.03);
+        assert_eq!(samples[249], 0.04);
+        assert_eq!(samples[250], 0.5);
+        assert_eq!(samples[350], 0.0);
+
+        std::fs::remove_file(&taken).unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,263 @@
+use piston_window::*;
+
+pub const NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+const FLAT_NAMES: [&str; 12] = [
+    "C", "Db", "D", "Eb", "E", "F", "Gb", "G", "Ab", "A", "Bb", "B",
+];
+const DEFAULT_A4: f32 = 440.0;
+
+// Deviation en cents de chaque degre par rapport au temperament egal, depuis la tonique
+const PYTHAGOREAN: [f32; 12] = [
+    0.0, 13.69, 3.91, -5.87, 7.82, -1.96, 11.73, 1.96, 15.64, 5.87, -3.91, 9.78,
+];
+const JUST: [f32; 12] = [
+    0.0, 11.73, 3.91, 15.64, -13.69, -1.96, -9.78, 1.96, 13.69, -15.64, 17.60, -11.73,
+];
+const MEANTONE: [f32; 12] = [
+    0.0, -23.95, -6.84, 10.26, -13.69, 3.42, -20.53, -3.42, -27.37, -10.26, 6.84, -17.11,
+];
+const WERCKMEISTER: [f32; 12] = [
+    0.0, -9.78, -7.82, -5.87, -9.78, -1.96, -11.73, -3.91, -7.82, -11.73, -3.91, -7.82,
+];
+
+// Ecart au-dela duquel l'aiguille passe a l'orange puis au rouge
+const IN_TUNE_CENTS: f32 = 5.0;
+const CLOSE_CENTS: f32 = 15.0;
+// Debattement de l'aiguille de part et d'autre de la verticale, pour 50 cents
+const GAUGE_ANGLE: f64 = std::f64::consts::PI / 3.0;
+
+pub struct Note {
+    pub name: &'static str,
+    pub octave: i32,
+    pub pitch_class: usize,
+    pub cents: f32,
+}
+
+// Nearest note for a frequency, given the A4 reference and a temperament
+// rooted on `tonic`. The temperament is shifted so that A keeps the reference.
+#[derive(Clone)]
+pub struct Tuner {
+    a4: f32,
+    tonic: usize,
+    offsets: [f32; 12],
+}
+
+impl Tuner {
+    // Reads a4=<Hz>, temperament=<equal|pythagorean|just|meantone|werckmeister> and tonic=<note>.
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let value = |key: &str| {
+            args.iter()
+                .find(|arg| arg.starts_with(key))
+                .and_then(|arg| arg.split('=').nth(1))
+        };
+
+        let a4 = match value("a4=") {
+            Some(a4) => a4
+                .parse::<f32>()
+                .ok()
+                .filter(|&a4| a4 > 0.0)
+                .ok_or_else(|| format!("Invalid a4 '{}'", a4))?,
+            None => DEFAULT_A4,
+        };
+        let offsets = match value("temperament=").unwrap_or("equal") {
+            "equal" => [0.0; 12],
+            "pythagorean" => PYTHAGOREAN,
+            "just" => JUST,
+            "meantone" => MEANTONE,
+            "werckmeister" => WERCKMEISTER,
+            other => return Err(format!("Unknown temperament '{}'", other)),
+        };
+        let tonic = match value("tonic=") {
+            Some(name) => NAMES
+                .iter()
+                .position(|n| n.eq_ignore_ascii_case(name))
+                .or_else(|| FLAT_NAMES.iter().position(|n| n.eq_ignore_ascii_case(name)))
+                .ok_or_else(|| format!("Invalid tonic '{}'", name))?,
+            None => 0,
+        };
+
+        Ok(Tuner { a4, tonic, offsets })
+    }
+
+    pub fn a4(&self) -> f32 {
+        self.a4
+    }
+
+    fn offset(&self, pitch_class: usize) -> f32 {
+        let degree = |pc: usize| self.offsets[(pc + 12 - self.tonic) % 12];
+        degree(pitch_class) - degree(9)
+    }
+
+    pub fn nearest(&self, frequency: f32) -> Option<Note> {
+        if frequency <= 0.0 || !frequency.is_finite() {
+            return None;
+        }
+        // Numero de note MIDI fractionnaire, A4 = 69
+        let semitones = 69.0 + 12.0 * (frequency / self.a4).log2();
+        let rounded = semitones.round() as i32;
+
+        // Avec un temperament inegal, la note la plus proche peut etre un voisin
+        (rounded - 1..=rounded + 1)
+            .map(|number| {
+                let pitch_class = number.rem_euclid(12) as usize;
+                let cents = (semitones - number as f32) * 100.0 - self.offset(pitch_class);
+                Note {
+                    name: NAMES[pitch_class],
+                    octave: number.div_euclid(12) - 1,
+                    pitch_class,
+                    cents,
+                }
+            })
+            .min_by(|a, b| a.cents.abs().total_cmp(&b.cents.abs()))
+    }
+}
+
+fn needle_color(cents: f32) -> [f32; 4] {
+    if cents.abs() < IN_TUNE_CENTS {
+        [0.0, 1.0, 0.0, 1.0]
+    } else if cents.abs() < CLOSE_CENTS {
+        [1.0, 0.5, 0.0, 1.0]
+    } else {
+        [1.0, 0.0, 0.0, 1.0]
+    }
+}
+
+// Needle gauge from -50 to +50 cents, with the twelve notes of the octave
+// under it. `note` is None while the pitch is not reliable.
+pub fn draw(note: Option<&Note>, width: f64, height: f64, c: Context, g: &mut G2d) {
+    let gray = [0.4, 0.4, 0.4, 1.0];
+    let center_x = width / 2.0;
+    let center_y = height * 0.65;
+    let radius = (width / 2.0).min(height * 0.55) * 0.85;
+    let point = |cents: f64, distance: f64| {
+        let angle = cents / 50.0 * GAUGE_ANGLE;
+        [
+            center_x + distance * angle.sin(),
+            center_y - distance * angle.cos(),
+        ]
+    };
+
+    // Graduations tous les 10 cents, plus longues au centre et aux extremites
+    for step in -5..=5 {
+        let cents = step as f64 * 10.0;
+        let inner = if step % 5 == 0 { 0.8 } else { 0.9 };
+        let [x1, y1] = point(cents, radius * inner);
+        let [x2, y2] = point(cents, radius);
+        line([0.8, 0.8, 0.8, 1.0], 1.5, [x1, y1, x2, y2], c.transform, g);
+    }
+
+    let (color, cents) = match note {
+        Some(note) => (needle_color(note.cents), note.cents.clamp(-50.0, 50.0)),
+        None => (gray, 0.0),
+    };
+    let [x, y] = point(cents as f64, radius * 0.95);
+    line(color, 2.5, [center_x, center_y, x, y], c.transform, g);
+    ellipse(
+        color,
+        [center_x - 6.0, center_y - 6.0, 12.0, 12.0],
+        c.transform,
+        g,
+    );
+
+    // Une case par note, les alterations en plus sombre comme sur un clavier
+    let cell = width / 12.0;
+    let top = height * 0.8;
+    for (pitch_class, name) in NAMES.iter().enumerate() {
+        let shade = if name.ends_with('#') { 0.15 } else { 0.3 };
+        let fill = match note {
+            Some(note) if note.pitch_class == pitch_class => color,
+            _ => [shade, shade, shade, 1.0],
+        };
+        rectangle(
+            fill,
+            [
+                pitch_class as f64 * cell + 2.0,
+                top,
+                cell - 4.0,
+                height * 0.12,
+            ],
+            c.transform,
+            g,
+        );
+    }
+}
+
+// Texte affiche dans la barre de titre, faute de police embarquee
+pub fn title(note: Option<&Note>, frequency: f32, a4: f32) -> String {
+    match note {
+        Some(note) => format!(
+            "Tuner - {}{} {:+.0} cents ({:.1} Hz, A4 = {} Hz)",
+            note.name, note.octave, note.cents, frequency, a4
+        ),
+        None => format!("Tuner - ... (A4 = {} Hz)", a4),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tuner(list: &[&str]) -> Tuner {
+        let args: Vec<String> = list.iter().map(|arg| arg.to_string()).collect();
+        Tuner::from_args(&args).unwrap()
+    }
+
+    #[test]
+    fn a4_keeps_the_reference_in_every_temperament() {
+        for temperament in ["equal", "pythagorean", "just", "meantone", "werckmeister"] {
+            for tonic in ["C", "D", "Eb", "A"] {
+                let tuner = tuner(&[
+                    &format!("temperament={}", temperament),
+                    &format!("tonic={}", tonic),
+                ]);
+                let note = tuner.nearest(440.0).unwrap();
+                assert_eq!(
+                    (note.name, note.octave),
+                    ("A", 4),
+                    "{} {}",
+                    temperament,
+                    tonic
+                );
+                assert!(
+                    note.cents.abs() < 0.01,
+                    "{} {}: {}",
+                    temperament,
+                    tonic,
+                    note.cents
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn equal_temperament_cents() {
+        let note = tuner(&[]).nearest(445.0).unwrap();
+        assert_eq!(note.name, "A");
+        assert!((note.cents - 19.56).abs() < 0.05, "{}", note.cents);
+
+        let note = tuner(&["a4=442"]).nearest(261.63 * 442.0 / 440.0).unwrap();
+        assert_eq!((note.name, note.octave), ("C", 4));
+        assert!(note.cents.abs() < 0.1, "{}", note.cents);
+    }
+
+    #[test]
+    fn just_major_third_is_flat_of_equal() {
+        let just = tuner(&["temperament=just"]);
+        let equal = tuner(&[]);
+        // Do juste avec A4 a 440 Hz, puis sa tierce majeure pure 5/4
+        let c = 440.0 * 2f32.powf((-9.0 + (JUST[0] - JUST[9]) / 100.0) / 12.0);
+        let e = c * 5.0 / 4.0;
+        for frequency in [c, e] {
+            assert!(just.nearest(frequency).unwrap().cents.abs() < 0.05);
+        }
+        let (c, e) = (equal.nearest(c).unwrap(), equal.nearest(e).unwrap());
+        assert_eq!((c.name, e.name), ("C", "E"));
+        assert!(
+            (e.cents - c.cents + 13.69).abs() < 0.05,
+            "{}",
+            e.cents - c.cents
+        );
+    }
+}
@@ -0,0 +1,521 @@
+use crate::analysis::Analysis;
+use hound::{SampleFormat, WavSpec, WavWriter};
+use piston_window::*;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::Range;
+use std::path::Path;
+
+// Bande ou se trouve l'essentiel de l'energie de la voix, fondamentale comprise
+const SPEECH_LOW: f32 = 80.0;
+const SPEECH_HIGH: f32 = 4000.0;
+// Part minimale de l'energie dans cette bande, et platitude maximale par
+// octave : un bruit est vers 0.5, une voix voisee bien en dessous
+const MIN_BAND_RATIO: f32 = 0.5;
+const MAX_FLATNESS: f32 = 0.4;
+// Seuil absolu, sous lequel rien n'est de la voix
+const MIN_DECIBELS: f32 = -60.0;
+// Le plancher de bruit remonte de 1 dB par seconde
+const FLOOR_RISE: f32 = 1.0;
+const DEFAULT_MARGIN: f32 = 10.0;
+const DEFAULT_HANGOVER: f64 = 0.3;
+// Hops actifs d'affilee avant de declarer un debut de parole
+const MIN_SPEECH_HOPS: u32 = 3;
+// Secondes gardees avant le debut des clips
+const CLIP_PREROLL: f64 = 0.2;
+// Images de l'historique affiche en bas de la fenetre
+const OVERLAY_FRAMES: usize = 300;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Segment {
+    pub start: f64,
+    pub end: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transition {
+    Started(f64),
+    Ended(Segment),
+}
+
+// Marks each hop as speech when it is well above the tracked noise floor,
+// has most of its energy in the speech band and a peaky (not noise-like)
+// spectrum there. Segments need a few active hops to start and end after a
+// hangover of inactive ones, which bridges the gaps between words.
+pub struct VoiceDetector {
+    hop_seconds: f64,
+    bin_width: f32,
+    margin: f32,
+    hangover_hops: u32,
+    noise_floor: Option<f32>,
+    hops: u64,
+    active_hops: u32,
+    quiet_hops: u32,
+    // Hop du debut et dernier hop actif du segment en cours
+    segment: Option<(u64, u64)>,
+}
+
+impl VoiceDetector {
+    pub fn new(
+        sample_rate: u32,
+        frame_size: usize,
+        hop_size: usize,
+        margin: f32,
+        hangover: f64,
+    ) -> Self {
+        let hop_seconds = hop_size as f64 / sample_rate as f64;
+        VoiceDetector {
+            hop_seconds,
+            bin_width: sample_rate as f32 / frame_size as f32,
+            margin,
+            hangover_hops: (hangover / hop_seconds).round() as u32,
+            noise_floor: None,
+            hops: 0,
+            active_hops: 0,
+            quiet_hops: 0,
+            segment: None,
+        }
+    }
+
+    pub fn is_speech(&self) -> bool {
+        self.segment.is_some()
+    }
+
+    fn bins(&self, low: f32, high: f32, len: usize) -> Range<usize> {
+        let bin = |frequency: f32| ((frequency / self.bin_width).round() as usize).min(len);
+        bin(low)..bin(high)
+    }
+
+    fn is_active(&self, decibels: f32, magnitudes: &[f32]) -> bool {
+        let floor = self.noise_floor.unwrap_or(decibels);
+        if decibels < MIN_DECIBELS || decibels < floor + self.margin {
+            return false;
+        }
+        let band = &magnitudes[self.bins(SPEECH_LOW, SPEECH_HIGH, magnitudes.len())];
+        if band.is_empty() {
+            return false;
+        }
+        let total: f32 = magnitudes.iter().map(|m| m * m).sum();
+        let in_band: f32 = band.iter().map(|m| m * m).sum();
+        total > 0.0
+            && in_band / total >= MIN_BAND_RATIO
+            && self.flatness(magnitudes) <= MAX_FLATNESS
+    }
+
+    // Spectral flatness (geometric over arithmetic mean of the power) of each
+    // octave of the speech band, weighted by the octave's energy: per octave,
+    // the slope of pink noise does not pass for structure.
+    fn flatness(&self, magnitudes: &[f32]) -> f32 {
+        let (mut weighted, mut energy) = (0.0, 0.0);
+        let mut low = SPEECH_LOW;
+        while low < SPEECH_HIGH {
+            let high = (low * 2.0).min(SPEECH_HIGH);
+            let bins = self.bins(low, high, magnitudes.len());
+            low = high;
+            let octave = &magnitudes[bins];
+            if octave.len() < 2 {
+                continue;
+            }
+            let power: f32 = octave.iter().map(|m| m * m).sum();
+            if power <= 0.0 {
+                continue;
+            }
+            let count = octave.len() as f32;
+            let log_mean = octave.iter().map(|m| (m * m).max(1e-20).ln()).sum::<f32>() / count;
+            weighted += log_mean.exp() / (power / count) * power;
+            energy += power;
+        }
+        if energy > 0.0 {
+            weighted / energy
+        } else {
+            1.0
+        }
+    }
+
+    pub fn push(&mut self, decibels: f32, magnitudes: &[f32]) -> Option<Transition> {
+        let active = self.is_active(decibels, magnitudes);
+        let hop = self.hops;
+        self.hops += 1;
+
+        // Le plancher descend tout de suite et remonte lentement, hors parole
+        if !active {
+            let rise = FLOOR_RISE * self.hop_seconds as f32;
+            self.noise_floor = Some(match self.noise_floor {
+                Some(floor) => decibels.min(floor + rise),
+                None => decibels,
+            });
+        }
+
+        if active {
+            self.active_hops += 1;
+            self.quiet_hops = 0;
+        } else {
+            self.active_hops = 0;
+            self.quiet_hops += 1;
+        }
+
+        match &mut self.segment {
+            None if self.active_hops >= MIN_SPEECH_HOPS => {
+                let start = hop + 1 - self.active_hops as u64;
+                self.segment = Some((start, hop));
+                Some(Transition::Started(start as f64 * self.hop_seconds))
+            }
+            Some((_, last)) if active => {
+                *last = hop;
+                None
+            }
+            Some(_) if self.quiet_hops > self.hangover_hops => self.finish().map(Transition::Ended),
+            _ => None,
+        }
+    }
+
+    // Ends the current segment, if any, at its last active hop.
+    pub fn finish(&mut self) -> Option<Segment> {
+        let (start, last) = self.segment.take()?;
+        Some(Segment {
+            start: start as f64 * self.hop_seconds,
+            end: (last + 1) as f64 * self.hop_seconds,
+        })
+    }
+}
+
+struct Clip {
+    path: String,
+    writer: WavWriter<BufWriter<File>>,
+}
+
+// Runs the detector on the input and exports the segments:
+// `vad=<file.csv>` writes start,end,duration lines and `vad_clips=<prefix>`
+// writes each segment to <prefix>_NNN.wav, with a short pre-roll and the
+// hangover at the end.
+pub struct VoiceActivity {
+    detector: VoiceDetector,
+    csv: Option<(String, BufWriter<File>)>,
+    clip_prefix: Option<String>,
+    spec: WavSpec,
+    preroll: VecDeque<f32>,
+    preroll_samples: usize,
+    clip: Option<Clip>,
+    next_number: u32,
+}
+
+impl VoiceActivity {
+    // Enabled by `vad`, `vad=<csv>` or `vad_clips=<prefix>`; tuned with
+    // `vad_margin=<dB>` and `vad_hangover=<s>`.
+    pub fn from_args(
+        args: &[String],
+        frame_size: usize,
+        hop_size: usize,
+        channels: usize,
+        sample_rate: u32,
+    ) -> Result<Option<Self>, String> {
+        let value = |key: &str| {
+            args.iter()
+                .find(|arg| arg.starts_with(key))
+                .and_then(|arg| arg.split('=').nth(1))
+        };
+        let number = |key: &str, default: f64| match value(key) {
+            Some(text) => text
+                .parse::<f64>()
+                .ok()
+                .filter(|&value| value >= 0.0)
+                .ok_or_else(|| format!("Invalid {}{}", key, text)),
+            None => Ok(default),
+        };
+
+        let csv_path = value("vad=").filter(|path| !path.is_empty());
+        let clip_prefix = value("vad_clips=").filter(|prefix| !prefix.is_empty());
+        if !args.iter().any(|arg| arg == "vad") && csv_path.is_none() && clip_prefix.is_none() {
+            return Ok(None);
+        }
+
+        let csv = match csv_path {
+            Some(path) => {
+                let file = File::create(path)
+                    .map_err(|err| format!("Failed to create {}: {}", path, err))?;
+                let mut writer = BufWriter::new(file);
+                writeln!(writer, "start,end,duration")
+                    .map_err(|err| format!("Failed to write {}: {}", path, err))?;
+                Some((path.to_string(), writer))
+            }
+            None => None,
+        };
+        let preroll_samples = (CLIP_PREROLL * sample_rate as f64) as usize * channels;
+        Ok(Some(VoiceActivity {
+            detector: VoiceDetector::new(
+                sample_rate,
+                frame_size,
+                hop_size,
+                number("vad_margin=", DEFAULT_MARGIN as f64)? as f32,
+                number("vad_hangover=", DEFAULT_HANGOVER)?,
+            ),
+            csv,
+            clip_prefix: clip_prefix.map(String::from),
+            spec: WavSpec {
+                channels: channels as u16,
+                sample_rate,
+                bits_per_sample: 32,
+                sample_format: SampleFormat::Float,
+            },
+            preroll: VecDeque::with_capacity(preroll_samples),
+            preroll_samples,
+            clip: None,
+            next_number: 1,
+        }))
+    }
+
+    pub fn is_speech(&self) -> bool {
+        self.detector.is_speech()
+    }
+
+    // `hop` holds the interleaved samples the analysis was run on.
+    pub fn push(
+        &mut self,
+        hop: &[f32],
+        analysis: &Analysis,
+        magnitudes: &[f32],
+    ) -> Result<(), String> {
+        let transition = self.detector.push(analysis.decibels, magnitudes);
+        if let Some(Transition::Started(_)) = transition {
+            self.start_clip()?;
+        }
+
+        match self.clip.as_mut() {
+            Some(clip) => {
+                for &sample in hop {
+                    clip.write(sample)?;
+                }
+            }
+            None if self.clip_prefix.is_some() => {
+                self.preroll.extend(hop);
+                let excess = self.preroll.len().saturating_sub(self.preroll_samples);
+                self.preroll.drain(..excess);
+            }
+            None => {}
+        }
+
+        if let Some(Transition::Ended(segment)) = transition {
+            self.end_segment(segment)?;
+        }
+        Ok(())
+    }
+
+    // Next <prefix>_NNN.wav that does not exist yet.
+    fn start_clip(&mut self) -> Result<(), String> {
+        let Some(prefix) = &self.clip_prefix else {
+            return Ok(());
+        };
+        let path = loop {
+            let path = format!("{}_{:03}.wav", prefix, self.next_number);
+            self.next_number += 1;
+            if !Path::new(&path).exists() {
+                break path;
+            }
+        };
+        let writer = WavWriter::create(&path, self.spec)
+            .map_err(|err| format!("Failed to create {}: {}", path, err))?;
+        let mut clip = Clip { path, writer };
+        for sample in self.preroll.drain(..) {
+            clip.write(sample)?;
+        }
+        self.clip = Some(clip);
+        Ok(())
+    }
+
+    fn end_segment(&mut self, segment: Segment) -> Result<(), String> {
+        println!(
+            "Speech: {:.2} - {:.2} s ({:.2} s)",
+            segment.start,
+            segment.end,
+            segment.end - segment.start
+        );
+        if let Some((path, writer)) = &mut self.csv {
+            writeln!(
+                writer,
+                "{:.3},{:.3},{:.3}",
+                segment.start,
+                segment.end,
+                segment.end - segment.start
+            )
+            .map_err(|err| format!("Failed to write {}: {}", path, err))?;
+        }
+        if let Some(clip) = self.clip.take() {
+            clip.writer
+                .finalize()
+                .map_err(|err| format!("Failed to write {}: {}", clip.path, err))?;
+            println!("Saved {}", clip.path);
+        }
+        Ok(())
+    }
+
+    // Closes the segment in progress and flushes the CSV.
+    pub fn finish(&mut self) -> Result<(), String> {
+        if let Some(segment) = self.detector.finish() {
+            self.end_segment(segment)?;
+        }
+        if let Some((path, writer)) = &mut self.csv {
+            writer
+                .flush()
+                .map_err(|err| format!("Failed to write {}: {}", path, err))?;
+        }
+        Ok(())
+    }
+}
+
+impl Clip {
+    fn write(&mut self, sample: f32) -> Result<(), String> {
+        self.writer
+            .write_sample(sample)
+            .map_err(|err| format!("Failed to write {}: {}", self.path, err))
+    }
+}
+
+// Speech indicator drawn over every view: a dot in the top right corner and
+// a strip along the bottom with the last frames, green for speech.
+pub struct Overlay {
+    history: VecDeque<bool>,
+}
+
+impl Overlay {
+    pub fn new() -> Self {
+        Overlay {
+            history: VecDeque::with_capacity(OVERLAY_FRAMES),
+        }
+    }
+
+    // Call once per frame.
+    pub fn update(&mut self, speech: bool) {
+        if self.history.len() == OVERLAY_FRAMES {
+            self.history.pop_front();
+        }
+        self.history.push_back(speech);
+    }
+
+    pub fn draw(&self, width: f64, height: f64, c: Context, g: &mut G2d) {
+        const SPEECH: [f32; 4] = [0.0, 0.9, 0.3, 1.0];
+        const SILENCE: [f32; 4] = [0.25, 0.25, 0.25, 1.0];
+        let speech = self.history.back().copied().unwrap_or(false);
+        ellipse(
+            if speech { SPEECH } else { SILENCE },
+            [width - 30.0, 10.0, 20.0, 20.0],
+            c.transform,
+            g,
+        );
+
+        let column = width / OVERLAY_FRAMES as f64;
+        let offset = OVERLAY_FRAMES - self.history.len();
+        for (i, &speech) in self.history.iter().enumerate() {
+            rectangle(
+                if speech { SPEECH } else { SILENCE },
+                [
+                    (offset + i) as f64 * column,
+                    height - 8.0,
+                    column.ceil(),
+                    8.0,
+                ],
+                c.transform,
+                g,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::{Analyzer, FRAME_SIZE, HOP_SIZE};
+    use crate::generator::{Generator, Signal};
+
+    const SAMPLE_RATE: u32 = 16000;
+
+    // Voyelle grossiere : fondamentale a 150 Hz et harmoniques decroissantes jusqu'a 3 kHz
+    fn vowel(t: f64) -> f32 {
+        (1..=20)
+            .map(|harmonic| {
+                let frequency = 150.0 * harmonic as f64;
+                (std::f64::consts::TAU * frequency * t).sin() / harmonic as f64
+            })
+            .sum::<f64>() as f32
+            * 0.1
+    }
+
+    fn segments(signal: impl Fn(usize) -> f32, seconds: f64) -> Vec<Segment> {
+        let mut analyzer = Analyzer::new(FRAME_SIZE, SAMPLE_RATE as f32);
+        let mut detector = VoiceDetector::new(SAMPLE_RATE, FRAME_SIZE, HOP_SIZE, 10.0, 0.3);
+        let mut segments = Vec::new();
+        let mut hop = vec![0.0; HOP_SIZE];
+        let hops = (seconds * SAMPLE_RATE as f64) as usize / HOP_SIZE;
+        for index in 0..hops {
+            for (i, sample) in hop.iter_mut().enumerate() {
+                *sample = signal(index * HOP_SIZE + i);
+            }
+            let analysis = analyzer.push(&hop);
+            if let Some(Transition::Ended(segment)) =
+                detector.push(analysis.decibels, analyzer.magnitudes())
+            {
+                segments.push(segment);
+            }
+        }
+        segments.extend(detector.finish());
+        segments
+    }
+
+    #[test]
+    fn finds_a_vowel_between_noise() {
+        let mut noise = Generator::new(Signal::White, SAMPLE_RATE, -50.0);
+        let noise: Vec<f32> = (0..3 * SAMPLE_RATE).map(|_| noise.next_sample()).collect();
+        let found = segments(
+            |n| {
+                let t = n as f64 / SAMPLE_RATE as f64;
+                let speech = if (1.0..2.0).contains(&t) {
+                    vowel(t)
+                } else {
+                    0.0
+                };
+                speech + noise[n]
+            },
+            3.0,
+        );
+        assert_eq!(found.len(), 1, "{:?}", found);
+        // La trame de 2048 echantillons retarde le debut et prolonge la fin
+        assert!((found[0].start - 1.0).abs() < 0.15, "{:?}", found[0]);
+        assert!((found[0].end - 2.0).abs() < 0.15, "{:?}", found[0]);
+    }
+
+    #[test]
+    fn loud_noise_is_not_speech() {
+        for signal in [Signal::White, Signal::Pink] {
+            let mut quiet = Generator::new(signal, SAMPLE_RATE, -50.0);
+            let mut loud = Generator::new(signal, SAMPLE_RATE, -10.0);
+            let noise: Vec<f32> = (0..2 * SAMPLE_RATE as usize)
+                .map(|n| {
+                    if n < SAMPLE_RATE as usize {
+                        quiet.next_sample()
+                    } else {
+                        loud.next_sample()
+                    }
+                })
+                .collect();
+            let found = segments(|n| noise[n], 2.0);
+            assert!(found.is_empty(), "{:?}: {:?}", signal, found);
+        }
+    }
+
+    #[test]
+    fn short_pauses_stay_in_the_segment() {
+        let found = segments(
+            |n| {
+                let t = n as f64 / SAMPLE_RATE as f64;
+                // Deux mots separes de 150 ms, moins que le hangover
+                if (0.5..1.0).contains(&t) || (1.15..1.6).contains(&t) {
+                    vowel(t)
+                } else {
+                    0.0
+                }
+            },
+            2.5,
+        );
+        assert_eq!(found.len(), 1, "{:?}", found);
+    }
+}